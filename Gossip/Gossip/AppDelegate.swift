@@ -47,14 +47,22 @@ class AppDelegate: UIResponder, UIApplicationDelegate {
     func applicationDidBecomeActive(_ application: UIApplication) {
         print("application did become active")
 //        AppHostWrapper.shared.app?.globalDispatch().emitAction(action: .wakeFromSleep);
-    
+        Task {
+            try? await RustApp.host?.enterForeground()
+        }
+    }
 
+    func applicationDidEnterBackground(_ application: UIApplication) {
+        print("application did enter background")
+        Task {
+            await RustApp.host?.enterBackground()
+        }
     }
 
     func applicationWillTerminate(_ application: UIApplication) {
         // Insert your cleanup code here
         print("Application is about to terminate.")
-        RustApp.host?.shutdown()
+        try? RustApp.host?.shutdown()
         print("i did it!")
         // Save data, close resources, etc.
     }
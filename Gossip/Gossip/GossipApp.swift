@@ -14,6 +14,7 @@ struct GossipApp: App {
     
     var globalModel: GlobalVM
     static var global: GlobalProtocol?
+    static var globalModel: GlobalVM?
     var photosViewVodel: PhotosViewVM
     
     
@@ -24,6 +25,7 @@ struct GossipApp: App {
         RustApp.startRustApp()
         self.globalModel = GlobalVM()
         self.photosViewVodel = PhotosViewVM()
+        GossipApp.globalModel = self.globalModel
         
         GossipApp.global = RustApp.host?.global(viewModel: globalModel)
     }
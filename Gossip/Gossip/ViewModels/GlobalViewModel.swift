@@ -25,6 +25,13 @@ class GlobalVM: GlobalViewModel, ObservableObject {
     
     var docData: DocData = DocData(docId: WideId(0))
     
+    var contacts: [Contact] = []
+    var friendNearby: Contact?
+    var directConversations: [ConversationSummary] = []
+    var directRequests: [ConversationRequest] = []
+    var identities: [Identity] = []
+    var lastError: GossipError?
+    
     func nameUpdated(name: String) async {
         self.name = name
     }
@@ -66,6 +73,39 @@ class GlobalVM: GlobalViewModel, ObservableObject {
         self.isBroadcasting =  broadcasting
     }
     
+    func contactsUpdated(contacts: [Contact]) async {
+        self.contacts = contacts
+    }
+    
+    func contactNearby(contact: Contact) async {
+        self.friendNearby = contact
+    }
+    
+    func directConversationsUpdated(conversations: [ConversationSummary]) async {
+        self.directConversations = conversations
+    }
+    
+    func directConversationRequestsUpdated(requests: [ConversationRequest]) async {
+        self.directRequests = requests
+    }
+    
+    func identitiesUpdated(identities: [Identity]) async {
+        self.identities = identities
+    }
+    
+    func backgroundError(error: GossipError) async {
+        self.lastError = error
+    }
+    
+    /// For calls made from the views, so their failures show up like background ones
+    func failed(_ error: Error) {
+        if let error = error as? GossipError {
+            self.lastError = error
+        } else {
+            print("unexpected error \(error)")
+        }
+    }
+    
     
     
     
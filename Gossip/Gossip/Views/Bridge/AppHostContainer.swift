@@ -37,7 +37,7 @@ class RustApp {
         
         if !isPreview {
             print("starting app host...")
-            let cfg = AppConfig(dataPath: getLibraryDataPath().path, logDirective: "ghostlib=debug", devApi: DeviceApiProvider())
+            let cfg = AppConfig(dataPath: getLibraryDataPath().path, logDirective: "libgossip=debug", devApi: DeviceApiProvider())
             host = AppHost(config: cfg)
         }
         
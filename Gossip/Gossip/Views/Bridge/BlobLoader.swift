@@ -61,7 +61,12 @@ class BlobLoader : ObservableObject, BlobDataResponder {
     
     private func hydrate() async {
         if blobHash != nil {
-            await RustApp.host?.blobs().hydrate(bdr: self)
+            do {
+                try await RustApp.host?.blobs().hydrate(bdr: self)
+            } catch {
+                state = .failed("\(error)")
+                GossipApp.globalModel?.failed(error)
+            }
         } else {
             state = .empty;
         }
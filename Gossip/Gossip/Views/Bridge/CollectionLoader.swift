@@ -24,7 +24,12 @@ class CollectionLoader {
             } else {
                 print("loaded collection details from thing")
                 Task {
-                    await GossipApp.global?.loadNearbyPayload(hash: collectionHash, collectionDelegate: delegate);
+                    do {
+                        try await GossipApp.global?.loadNearbyPayload(hash: collectionHash, collectionDelegate: delegate);
+                    } catch {
+                        await delegate.update(state: .failed("\(error)"))
+                        await GossipApp.globalModel?.failed(error)
+                    }
                 }
             }
         } else {
@@ -17,7 +17,7 @@ struct ControlsContainer: View {
             Section {
                 WideButton(text: "Delete All Data", backgroundColor: .red, action: {
                     RustApp.host?.setResetFlag();
-                    RustApp.host?.shutdown();
+                    try? RustApp.host?.shutdown();
                     exit(0);
                 })
                 WideButton(text: "Sync peers", backgroundColor: .blue, action: {
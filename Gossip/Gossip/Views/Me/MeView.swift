@@ -68,7 +68,11 @@ struct MeView: View {
         }
         .onChange(of: status) {
             Task {
-                await GossipApp.global?.setStatus(status: status)
+                do {
+                    try await GossipApp.global?.setStatus(status: status)
+                } catch {
+                    global.failed(error)
+                }
             }
         }
         .onChange(of: global.status) {
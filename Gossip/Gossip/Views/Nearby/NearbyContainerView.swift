@@ -142,9 +142,13 @@ struct NearbyContainerView: View {
                                         attachmentDirStr = attachmentDir.path()
                                     }
                                     
-                                    await GossipApp.global?.sendMessage(text:composingMessage, payloadDir: attachmentDirStr);
-                                    composingMessage = ""
-                                    attachments = []
+                                    do {
+                                        try await GossipApp.global?.sendMessage(text:composingMessage, payloadDir: attachmentDirStr);
+                                        composingMessage = ""
+                                        attachments = []
+                                    } catch {
+                                        model.failed(error)
+                                    }
                                 }
                             }
                             .navigationTitle("Chat")
@@ -203,7 +207,11 @@ struct NearbyContainerView: View {
             statusDebounceTimer?.invalidate()
             statusDebounceTimer = Timer.scheduledTimer(withTimeInterval: 1.5, repeats: false) { _ in
                 Task {
-                    await GossipApp.global?.setStatus(status: status)
+                    do {
+                        try await GossipApp.global?.setStatus(status: status)
+                    } catch {
+                        await model.failed(error)
+                    }
                 }
             }
             
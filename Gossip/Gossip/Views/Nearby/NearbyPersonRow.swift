@@ -53,7 +53,7 @@ struct NearbyPersonRow: View {
 }
 
 func nearbyProfileDummy() -> NearbyProfile {
-    return NearbyProfile(pk: WideId(0), name: "Crowbert", pic: WideId(1), status: Status(text: "caw! caw! caw!"))
+    return NearbyProfile(pk: WideId(0), name: "Crowbert", pic: WideId(1), status: Status(text: "caw! caw! caw!"), isContact: false)
 }
 
 #Preview {
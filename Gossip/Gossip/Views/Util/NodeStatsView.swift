@@ -23,7 +23,7 @@ struct NodeStatsView: View {
                         Text(la)
                     }
                 }}
-                HStack { Text("relay url"); Spacer();     Text(stats.relayUrl)}
+                HStack { Text("relay url"); Spacer();     Text(stats.relayUrl ?? "no relay")}
                 Group {
                     ForEach(stats.connections, id: \.nodeId) { conn in
                         VStack {
@@ -31,18 +31,18 @@ struct NodeStatsView: View {
                             Text("")
                             HStack { Text("nodeid").bold(); Spacer();        Text(conn.nodeId.toString())}
                             Divider()
-                            HStack { Text("conn type"); Spacer();     Text(conn.connType)}
+                            HStack { Text("conn type"); Spacer();     Text(describe(conn.connType))}
                             Divider()
-                            HStack { Text("last received"); Spacer(); Text(String(conn.lastReceived))}
-                            Divider()
-                            HStack { Text("relay info"); Spacer();    Text(conn.relayInfo)}
+                            HStack { Text("last received"); Spacer(); Text(describe(conn.lastReceived))}
                             Divider()
+                            if let relay = conn.relay {
+                                HStack { Text("relay info"); Spacer();    Text("\(relay.url) (latency: \(describe(relay.latencyMs)), last alive: \(describe(relay.lastAlive)))")}
+                                Divider()
+                            }
 //                            HStack { Text("has send a"); Spacer();    Text(String(conn.hasSendAddr))}
-                            HStack { Text("relay info"); Spacer();    Text(conn.relayInfo)}
-                            Divider()
                             HStack(alignment: .top) { Text("addrs"); Spacer();    VStack(alignment:.trailing) {
-                                ForEach(conn.addrs, id: \.self) { addr in
-                                    Text(addr)
+                                ForEach(conn.addrs, id: \.addr) { addr in
+                                    Text("\(addr.addr) (lat: \(describe(addr.latencyMs)), last payload: \(describe(addr.lastPayload)))")
                                 }
                             }}
                             
@@ -54,11 +54,32 @@ struct NodeStatsView: View {
             }
         }
         .onAppear {
-            nodeStat = RustApp.host?.nodeStats(viewModel: self.viewModel)
+            do {
+                nodeStat = try RustApp.host?.nodeStats(viewModel: self.viewModel)
+            } catch {
+                GossipApp.globalModel?.failed(error)
+            }
         }
     }
 }
 
+private func describe(_ connType: ConnType) -> String {
+    switch connType {
+    case .direct(let addr): return "Direct (\(addr))"
+    case .relay(let url): return "Relay (\(url))"
+    case .mixed(let addr, let url): return "Mixed (\(addr), \(url))"
+    case .none: return "none"
+    }
+}
+
+private func describe(_ ms: UInt64?) -> String {
+    ms.map { "\($0)ms" } ?? "∞"
+}
+
+private func describe(_ interval: TimeInterval?) -> String {
+    interval.map { "\(Int($0 * 1000))ms" } ?? "∞"
+}
+
 #Preview {
     List {
         NodeStatsView()
@@ -0,0 +1,144 @@
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::Result;
+use futures_util::StreamExt;
+use iroh::docs::store::Query;
+use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::Notify;
+use tracing::warn;
+
+use crate::contacts::model::{Contact, contact_key, CONTACT_PREFIX, ContactsServiceEvents};
+use crate::contacts::model::ContactsServiceEvents::ContactsUpdated;
+use crate::data::PublicKey;
+use crate::doc::{Doc, key_of};
+use crate::events::{broadcast, create_broadcast, subscribe_with_snapshot};
+use crate::nearby::model::NearbyProfile;
+use crate::views::errors::GossipError;
+
+pub use self::Service as ContactsService;
+
+pub mod model;
+
+/// Contacts live in the root settings doc, so unlike everything the nearby
+/// service knows about other people they survive leaving a group.
+#[derive(Clone)]
+pub struct Service(Arc<InnerService>);
+impl Deref for Service {
+    type Target = InnerService;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+pub struct InnerService {
+    bc: Sender<ContactsServiceEvents>,
+    doc: Doc,
+    stop: Notify
+}
+
+impl Service {
+    pub fn new(doc: Doc) -> Service {
+        let s = Service(Arc::new(InnerService {
+            bc: create_broadcast(),
+            doc,
+            stop: Notify::new(),
+        }));
+        let o = s.clone();
+        tokio::spawn(async move { o.observe_doc().await });
+        s
+    }
+}
+
+impl InnerService {
+    pub fn subscribe(&self) -> Receiver<ContactsServiceEvents> {
+        self.bc.subscribe()
+    }
+
+    pub async fn subscribe_with_snapshot(&self) -> Result<(Vec<Contact>, Receiver<ContactsServiceEvents>)> {
+        subscribe_with_snapshot(&self.bc, || self.list_contacts()).await
+    }
+
+    async fn observe_doc(&self) -> Result<()> {
+        let mut stream = self.doc.subscribe().await?;
+        loop {
+            let e = tokio::select! {
+                e = stream.next() => match e {
+                    Some(e) => e,
+                    None => break
+                },
+                _ = self.stop.notified() => break
+            };
+            if key_of(&e.entry).starts_with(CONTACT_PREFIX) {
+                match self.list_contacts().await {
+                    Ok(contacts) => {
+                        broadcast(&self.bc, ContactsUpdated(contacts))?;
+                    }
+                    Err(err) => warn!(author = %e.entry.author(), "couldn't read changed contacts: {err:#}")
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Ends observe_doc
+    pub fn shutdown(&self) {
+        self.stop.notify_one();
+    }
+
+    pub async fn list_contacts(&self) -> Result<Vec<Contact>> {
+        self.doc.read_blobs_by_query(Query::author(self.doc.me().await).key_prefix(CONTACT_PREFIX)).await
+    }
+
+    pub async fn get_contact(&self, pk: &PublicKey) -> Result<Option<Contact>> {
+        self.doc.read_own_blob(contact_key(*pk).as_str()).await
+    }
+
+    pub async fn is_contact(&self, pk: &PublicKey) -> Result<bool> {
+        Ok(self.get_contact(pk).await?.is_some())
+    }
+
+    pub async fn save_contact(&self, profile: &NearbyProfile, note: String) -> Result<Contact> {
+        // re-saving someone shouldn't throw away having verified them
+        let verified = self.get_contact(&profile.pk).await?.is_some_and(|c| c.verified);
+        let contact = Contact::from_profile(profile, note, verified);
+        self.put_contact(&contact).await?;
+        Ok(contact)
+    }
+
+    pub async fn set_verified(&self, pk: &PublicKey, verified: bool) -> Result<()> {
+        let mut contact = self.get_contact(pk).await?.ok_or_else(|| GossipError::not_found(format!("{pk} is not a contact")))?;
+        contact.verified = verified;
+        self.put_contact(&contact).await
+    }
+
+    pub async fn set_note(&self, pk: &PublicKey, note: String) -> Result<()> {
+        let mut contact = self.get_contact(pk).await?.ok_or_else(|| GossipError::not_found(format!("{pk} is not a contact")))?;
+        contact.note = note;
+        self.put_contact(&contact).await
+    }
+
+    /// Refresh the last known name and pic of a contact we just saw in a group
+    pub async fn contact_seen(&self, profile: &NearbyProfile) -> Result<Option<Contact>> {
+        if let Some(mut contact) = self.get_contact(&profile.pk).await? {
+            contact.name = profile.name.clone();
+            contact.pic = profile.pic;
+            contact.last_seen = SystemTime::now();
+            self.put_contact(&contact).await?;
+            Ok(Some(contact))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn remove_contact(&self, pk: &PublicKey) -> Result<()> {
+        self.doc.del(self.doc.me().await, contact_key(*pk)).await?;
+        Ok(())
+    }
+
+    async fn put_contact(&self, contact: &Contact) -> Result<()> {
+        self.doc.write_keyed_blob(contact_key(contact.pk).as_str(), contact.clone()).await?;
+        Ok(())
+    }
+}
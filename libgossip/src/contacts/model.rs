@@ -0,0 +1,44 @@
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::data::{BlobHash, PublicKey};
+use crate::nearby::model::NearbyProfile;
+use crate::record::Record;
+
+pub const CONTACT_PREFIX: &str = "contacts/by_pk";
+
+pub fn contact_key(pk: PublicKey) -> String {
+    format!("{CONTACT_PREFIX}/{pk}")
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[derive(uniffi::Record)]
+pub struct Contact {
+    pub pk: PublicKey,
+    pub name: String,
+    pub pic: Option<BlobHash>,
+    pub note: String,
+    pub verified: bool,
+    pub last_seen: SystemTime
+}
+
+impl Record for Contact {}
+
+impl Contact {
+    pub fn from_profile(profile: &NearbyProfile, note: String, verified: bool) -> Self {
+        Contact {
+            pk: profile.pk,
+            name: profile.name.clone(),
+            pic: profile.pic,
+            note,
+            verified,
+            last_seen: SystemTime::now()
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ContactsServiceEvents {
+    ContactsUpdated(Vec<Contact>)
+}
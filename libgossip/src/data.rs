@@ -1,5 +1,6 @@
 use std::{fmt, ptr};
 use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
 
 use iroh::base::base32;
 use iroh::blobs::format::collection::Collection;
@@ -118,6 +119,14 @@ impl Display for WideId {
     }
 }
 
+impl FromStr for WideId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(base32::parse_array::<32>(s)?.into())
+    }
+}
+
 impl Debug for WideId {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "{}", base32::fmt(self.to_bytes()))
@@ -159,6 +168,12 @@ impl From<WideId> for NamespaceId {
     }
 }
 
+/// For ids in json meant to be read by people, which would otherwise come out as
+/// their four u64s
+pub fn as_string<T: Display, S: serde::Serializer>(value: &T, s: S) -> Result<S::Ok, S::Error> {
+    s.collect_str(value)
+}
+
 pub async fn collection_from_dir(doc: &Doc, payload_dir: &str) -> anyhow::Result<BlobHash> {
     let mut hashes: Vec<(String, Hash)> = vec![];
     let mut read_stream = tokio::fs::read_dir(payload_dir).await?;
@@ -1,4 +1,6 @@
 use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::Relaxed;
 
 use crate::ble::{BLEGossipBroadcaster, BLEGossipScanner, GossipScannerDelegate};
 
@@ -11,28 +13,60 @@ pub trait DeviceApiServiceProvider: Send + Sync {
 
 #[uniffi::export]
 pub fn create_dummy_provider() -> Arc<dyn DeviceApiServiceProvider> {
-    Arc::new(DummyApiServiceProvider { })
+    Arc::new(DummyApiServiceProvider::new())
 }
-pub struct DummyApiServiceProvider;
+
+/// Does nothing, but remembers whether it was asked to scan or broadcast so
+/// tests can check what the services did with BLE
+#[derive(Clone)]
+pub struct DummyApiServiceProvider {
+    scanner: Arc<DummyBLEScanner>,
+    broadcaster: Arc<DummyBLEBroadcaster>
+}
+
+impl DummyApiServiceProvider {
+    pub fn new() -> Self {
+        DummyApiServiceProvider {
+            scanner: Arc::new(DummyBLEScanner { scanning: AtomicBool::new(false) }),
+            broadcaster: Arc::new(DummyBLEBroadcaster { broadcasting: AtomicBool::new(false) })
+        }
+    }
+}
+
+#[cfg(test)]
+impl DummyApiServiceProvider {
+    pub fn is_scanning(&self) -> bool {
+        self.scanner.scanning.load(Relaxed)
+    }
+
+    pub fn is_broadcasting(&self) -> bool {
+        self.broadcaster.broadcasting.load(Relaxed)
+    }
+}
+
 impl DeviceApiServiceProvider for DummyApiServiceProvider {
     fn ble_scanner(&self) -> Arc<dyn BLEGossipScanner> {
-        Arc::new(DummyBLEScanner{})
+        self.scanner.clone()
     }
 
     fn ble_broadcaster(&self) -> Arc<dyn BLEGossipBroadcaster> {
-        Arc::new(DummyBLEBroadcaster{})
+        self.broadcaster.clone()
     }
 }
 
-struct DummyBLEScanner;
-struct DummyBLEBroadcaster;
+struct DummyBLEScanner {
+    scanning: AtomicBool
+}
+struct DummyBLEBroadcaster {
+    broadcasting: AtomicBool
+}
 impl BLEGossipScanner for DummyBLEScanner {
     fn start_scanning(&self) {
-
+        self.scanning.store(true, Relaxed);
     }
 
     fn stop_scanning(&self) {
-
+        self.scanning.store(false, Relaxed);
     }
 
     fn set_delegate(&self, _delegate: Arc<GossipScannerDelegate>) {
@@ -41,11 +75,11 @@ impl BLEGossipScanner for DummyBLEScanner {
 }
 impl BLEGossipBroadcaster for DummyBLEBroadcaster {
     fn start(&self) {
-
+        self.broadcasting.store(true, Relaxed);
     }
 
     fn stop(&self) {
-
+        self.broadcasting.store(false, Relaxed);
     }
 
     fn set_document_data(&self, _document_data: Vec<u8>) {
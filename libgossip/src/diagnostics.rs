@@ -0,0 +1,111 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures_lite::StreamExt;
+use iroh::blobs::format::collection::Collection;
+use iroh::blobs::util::SetTagOption;
+use iroh::docs::store::Query;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::data::BlobHash;
+use crate::doc::{Doc, Node};
+use crate::events::missed_events;
+use crate::logging;
+use crate::nearby::model::NearbyDiagnostics;
+use crate::nearby::NearbyService;
+use crate::nearby::timeline::{Timeline, TimelineEntry};
+use crate::quarantine::Quarantine;
+use crate::settings::Service as SettingsService;
+use crate::views::node_stat::{generate_stats, NodeStatsData};
+
+pub const DIAGNOSTICS_JSON: &str = "diagnostics.json";
+pub const DIAGNOSTICS_LOG: &str = "gossip.log";
+// enough for the last few minutes of debug logging
+const MAX_LOG_BYTES: u64 = 512 * 1024;
+
+/// Everything we can find out about why a device isn't doing what it should.
+/// Parts that can't be gathered say why instead of failing the whole thing,
+/// a half broken node is exactly when this gets made.
+#[derive(Serialize)]
+struct Diagnostics {
+    created_at: DateTime<Utc>,
+    node: Section<NodeStatsData>,
+    nearby: Section<Option<NearbyDiagnostics>>,
+    docs: Section<Vec<DocDiagnostics>>,
+    settings: Section<Value>,
+    timeline: Vec<TimelineEntry>,
+    missed_events: u64,
+    quarantined: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Section<T> {
+    Ok(T),
+    Failed { error: String }
+}
+
+impl<T> From<Result<T>> for Section<T> {
+    fn from(value: Result<T>) -> Self {
+        match value {
+            Ok(v) => Section::Ok(v),
+            Err(e) => Section::Failed { error: format!("{e:#}") }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DocDiagnostics {
+    id: String,
+    capability: String,
+    entries: usize,
+    known_peers: usize,
+    active_peers: u32,
+}
+
+/// Gathers the diagnostics and the tail of the log into a collection, so the hash
+/// can be sent to someone like any other payload
+pub async fn create_bundle(node: &Node, settings: &SettingsService, nearby: &NearbyService, quarantine: &Quarantine, timeline: &Timeline) -> Result<BlobHash> {
+    let diagnostics = Diagnostics {
+        created_at: Utc::now(),
+        node: generate_stats(node).await.into(),
+        nearby: Section::Ok(nearby.diagnostics().await),
+        docs: list_docs(node).await.into(),
+        settings: settings.diagnostics_json().await.into(),
+        timeline: timeline.list().await,
+        missed_events: missed_events(),
+        quarantined: quarantine.list().await.into_iter()
+            .map(|q| format!("{} {} by {}: {}", q.doc_id, q.key, q.author, q.reason))
+            .collect(),
+    };
+    let logs = logging::recent_logs(MAX_LOG_BYTES).unwrap_or_else(|e| format!("no logs: {e:#}"));
+
+    let json = node.blobs().add_bytes(serde_json::to_vec_pretty(&diagnostics)?).await?;
+    let log = node.blobs().add_bytes(logs.into_bytes()).await?;
+    let collection: Collection = [
+        (String::from(DIAGNOSTICS_JSON), json.hash),
+        (String::from(DIAGNOSTICS_LOG), log.hash)
+    ].into_iter().collect();
+    let (hash, _) = node.blobs().create_collection(collection, SetTagOption::Auto, vec![]).await?;
+    Ok(hash.into())
+}
+
+async fn list_docs(node: &Node) -> Result<Vec<DocDiagnostics>> {
+    let mut docs = vec![];
+    let mut stream = node.docs().list().await?;
+    while let Some(item) = stream.next().await {
+        let (id, capability) = item?;
+        let Some(core) = node.docs().open(id).await? else {
+            continue;
+        };
+        let doc = Doc::new(core, node.clone());
+        docs.push(DocDiagnostics {
+            id: id.to_string(),
+            capability: format!("{capability:?}"),
+            entries: doc.list_entries_by_query(Query::all()).await?.len(),
+            known_peers: doc.get_peer_nodes().await.len(),
+            active_peers: doc.get_active_connected_peer_count().await?,
+        });
+    }
+    Ok(docs)
+}
@@ -0,0 +1,189 @@
+use std::ops::Deref;
+use std::sync::{Arc, Weak};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures_lite::StreamExt;
+use iroh::docs::{Author, Capability, DocTicket, NamespaceId};
+use iroh::net::NodeAddr;
+use iroh::net::key::PublicKey as NodeId;
+use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::{Notify, RwLock};
+use tracing::{info, warn};
+
+use crate::data::PublicKey;
+use crate::direct::model::{ConversationEvents, ConversationRecord, direct_namespace};
+use crate::direct::model::ConversationEvents::{AllMessagesUpdated, ReceivedOneMessage};
+use crate::doc::{Doc, key_of, Node};
+use crate::events::{broadcast, create_broadcast, subscribe_with_snapshot, WeakService};
+use crate::group_key::GroupKey;
+use crate::nearby::model::{display_msg_map, DisplayMessage, Post};
+use crate::nearby::post::{PostDomain, PostDomainResponder};
+use crate::quarantine::Quarantine;
+
+/// One private conversation, backed by its own two member doc
+#[derive(Clone)]
+pub struct Conversation(Arc<InnerConversation>);
+
+impl Deref for Conversation {
+    type Target = InnerConversation;
+    fn deref(&self) -> &Self::Target { self.0.as_ref() }
+}
+
+pub struct InnerConversation {
+    bc: Sender<ConversationEvents>,
+    me: PublicKey,
+    record: ConversationRecord,
+    doc: Doc,
+    doc_stop: Arc<Notify>,
+    // needs the Arc to exist before it can be built, so its filled in by open
+    messages: RwLock<Option<PostDomain<Conversation, InnerConversation>>>,
+}
+
+impl Drop for InnerConversation {
+    fn drop(&mut self) {
+        self.doc_stop.notify_waiters();
+    }
+}
+
+#[async_trait]
+impl PostDomainResponder for Conversation {
+    async fn all_posts_updated(&self, posts: Vec<Post>) -> Result<()> {
+        let dms: Vec<DisplayMessage> = posts.into_iter().enumerate()
+            .map(|(i, m)| { display_msg_map(i, &self.me, m) })
+            .collect();
+        broadcast(&self.bc, AllMessagesUpdated(dms))?;
+        Ok(())
+    }
+
+    async fn one_post_updated(&self, count: usize, post: Post) -> Result<()> {
+        let dm: DisplayMessage = display_msg_map(count - 1, &self.me, post);
+        broadcast(&self.bc, ReceivedOneMessage(dm))?;
+        Ok(())
+    }
+}
+
+impl WeakService<InnerConversation, Conversation> for Conversation {
+    fn get_weak(&self) -> Weak<InnerConversation> {
+        Arc::downgrade(&self.0)
+    }
+
+    fn from_weak(weak: &Weak<InnerConversation>) -> Option<Conversation> {
+        weak.upgrade().map(Conversation)
+    }
+}
+
+impl Conversation {
+    pub async fn open(node: &Node, me: &Author, record: ConversationRecord, quarantine: Quarantine) -> Result<Conversation> {
+        let secret = direct_namespace(me, record.pk)?;
+        if secret.id() != NamespaceId::from(record.doc_id) {
+            return Err(anyhow!("conversation with {} does not match its derived namespace", record.pk));
+        }
+        let capability = Capability::Write(secret);
+        let group_key = GroupKey::from_capability(&capability)?;
+
+        // importing an already known namespace just reopens it and kicks off sync
+        let peer = NodeAddr::new(NodeId::from_bytes(&record.peer_node.to_bytes())?);
+        let core = node.docs().import(DocTicket { capability, nodes: vec![peer] }).await?;
+        let doc = Doc::new(core, node.clone());
+
+        let conversation = Conversation(Arc::new(InnerConversation {
+            bc: create_broadcast(),
+            me: me.id().into(),
+            record,
+            doc: doc.clone(),
+            doc_stop: Arc::new(Notify::new()),
+            messages: RwLock::new(None),
+        }));
+
+        let mut messages = PostDomain::new(&doc, group_key, quarantine, &conversation);
+        messages.initialize().await?;
+        *conversation.messages.write().await = Some(messages);
+
+        conversation.listen().await?;
+        Ok(conversation)
+    }
+
+    async fn listen(&self) -> Result<()> {
+        let mut stream = self.doc.subscribe().await?;
+        let doc_stop = self.doc_stop.clone();
+        let weak = self.get_weak();
+        let peer = self.record.pk;
+        let doc_id = self.doc.id();
+        tokio::spawn(async move {
+            info!(%peer, doc = %doc_id, "listening to direct conversation");
+            loop {
+                tokio::select! {
+                    Some(e) = stream.next() => {
+                        let Some(s) = Self::from_weak(&weak) else { break };
+                        let mut lock = s.messages.write().await;
+                        if let Some(ref mut messages) = *lock {
+                            if messages.handles(&key_of(&e.entry)) {
+                                if let Err(e) = messages.insert_entry(e).await {
+                                    warn!(%peer, doc = %doc_id, "error handling direct message: {e:#}")
+                                }
+                            }
+                        }
+                    }
+                    _ = doc_stop.notified() => {
+                        break;
+                    }
+                }
+            }
+            info!(%peer, doc = %doc_id, "stopped listening to direct conversation");
+        });
+        Ok(())
+    }
+}
+
+impl InnerConversation {
+    pub fn subscribe(&self) -> Receiver<ConversationEvents> {
+        self.bc.subscribe()
+    }
+
+    pub async fn subscribe_with_snapshot(&self) -> Result<(Vec<DisplayMessage>, Receiver<ConversationEvents>)> {
+        subscribe_with_snapshot(&self.bc, || async { Ok(self.all_messages().await) }).await
+    }
+
+    pub fn record(&self) -> &ConversationRecord {
+        &self.record
+    }
+
+    pub fn peer_node(&self) -> Result<NodeId> {
+        Ok(NodeId::from_bytes(&self.record.peer_node.to_bytes())?)
+    }
+
+    pub async fn start_sync(&self) -> Result<()> {
+        let mut nodes = self.doc.get_peer_nodes().await;
+        if nodes.is_empty() {
+            nodes.push(NodeAddr::new(self.peer_node()?));
+        }
+        self.doc.start_sync(nodes).await?;
+        Ok(())
+    }
+
+    /// Stops listening and closes the doc, for shutting down
+    pub async fn close(&self) -> Result<()> {
+        self.doc_stop.notify_one();
+        self.doc.close().await
+    }
+
+    pub async fn all_messages(&self) -> Vec<DisplayMessage> {
+        let lock = self.messages.read().await;
+        match *lock {
+            Some(ref messages) => messages.posts().await.into_iter().enumerate()
+                .map(|(i, m)| { display_msg_map(i, &self.me, m) })
+                .collect(),
+            None => vec![]
+        }
+    }
+
+    pub async fn send_message(&self, text: String) -> Result<()> {
+        let post = Post::new(self.me).body(text);
+        let lock = self.messages.read().await;
+        if let Some(ref messages) = *lock {
+            messages.create_post(post).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+use std::sync::{Arc, Weak};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use iroh::docs::store::Query;
+use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::data::{PublicKey, WideId};
+use crate::direct::conversation::Conversation;
+use crate::direct::model::{ConversationRecord, ConversationRequest, ConversationSummary, direct_key, direct_namespace, DIRECT_PREFIX, DirectServiceEvents};
+use crate::direct::model::DirectServiceEvents::{ConversationsUpdated, RequestsUpdated};
+use crate::doc::{Doc, Node};
+use crate::events::{broadcast, create_broadcast, start_with, Starter, Subscriber, subscribe_with_snapshot, WeakService};
+use crate::identity::IdentityService;
+use crate::nearby::{NearbyService, NearbyServiceEvents};
+use crate::quarantine::Quarantine;
+use crate::views::errors::GossipError;
+
+pub use self::Service as DirectService;
+
+pub mod model;
+pub mod conversation;
+
+/// Private one to one conversations. Each one is its own doc, kept open and
+/// synced with the peer's node directly, so it doesn't care which nearby group
+/// (if any) we're in or whether BLE is on.
+#[derive(Clone)]
+pub struct Service(Arc<InnerService>);
+
+impl Deref for Service {
+    type Target = InnerService;
+    fn deref(&self) -> &Self::Target { self.0.as_ref() }
+}
+
+pub struct InnerService {
+    bc: Sender<DirectServiceEvents>,
+    node: Node,
+    root_doc: Doc,
+    identity_service: IdentityService,
+    nearby_service: NearbyService,
+    quarantine: Quarantine,
+    conversations: RwLock<HashMap<PublicKey, Conversation>>,
+    // waiting on the user to accept or decline
+    requests: RwLock<HashMap<PublicKey, ConversationRequest>>,
+    // not asked about again until the next launch
+    declined: RwLock<HashSet<PublicKey>>,
+}
+
+#[async_trait]
+impl Starter for Service {
+    async fn start(&self) -> Result<()> {
+        let records: Vec<ConversationRecord> = self.root_doc.read_blobs_by_query(
+            Query::author(self.root_doc.me().await).key_prefix(DIRECT_PREFIX)
+        ).await?;
+        for record in records {
+            let pk = record.pk;
+            match self.open_conversation(record).await {
+                Ok(c) => {
+                    if let Err(e) = c.start_sync().await {
+                        warn!(peer = %pk, "couldn't start syncing direct conversation: {e:#}");
+                    }
+                }
+                Err(e) => warn!(peer = %pk, "couldn't reopen direct conversation: {e:#}")
+            }
+        }
+        self.broadcast_conversations().await?;
+        self.listen_bc(self.nearby_service.subscribe());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Subscriber<NearbyServiceEvents, InnerService, Service> for Service {
+    async fn event(&self, event: NearbyServiceEvents) -> Result<()> {
+        if let NearbyServiceEvents::DirectConversationRequested(pk, name, peer_node, me) = event {
+            if self.conversations.read().await.contains_key(&pk) || self.declined.read().await.contains(&pk) {
+                return Ok(());
+            }
+            let added = self.requests.write().await
+                .insert(pk, ConversationRequest { pk, name, peer_node, me })
+                .is_none();
+            if added {
+                info!(peer = %pk, "asked to talk privately, waiting for the user");
+                self.broadcast_requests().await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WeakService<InnerService, Service> for Service {
+    fn get_weak(&self) -> Weak<InnerService> {
+        Arc::downgrade(&self.0)
+    }
+
+    fn from_weak(weak: &Weak<InnerService>) -> Option<Service> {
+        weak.upgrade().map(Service)
+    }
+}
+
+impl Service {
+    pub fn new(node: Node, root_doc: Doc, identity_service: IdentityService, nearby_service: NearbyService, quarantine: Quarantine) -> Self {
+        start_with(Service(Arc::new(InnerService {
+            bc: create_broadcast(),
+            node,
+            root_doc,
+            identity_service,
+            nearby_service,
+            quarantine,
+            conversations: RwLock::new(HashMap::new()),
+            requests: RwLock::new(HashMap::new()),
+            declined: RwLock::new(HashSet::new()),
+        })))
+    }
+
+    pub fn subscribe(&self) -> Receiver<DirectServiceEvents> {
+        self.bc.subscribe()
+    }
+
+    pub async fn subscribe_with_snapshot(&self) -> Result<(Vec<ConversationSummary>, Receiver<DirectServiceEvents>)> {
+        subscribe_with_snapshot(&self.bc, || async { Ok(self.list_conversations().await) }).await
+    }
+
+    /// Closes every conversation, only for when the app host is going away
+    pub async fn shutdown(&self) -> Result<()> {
+        let conversations: Vec<Conversation> = self.conversations.write().await.drain().map(|(_, c)| c).collect();
+        for c in conversations {
+            c.close().await?;
+        }
+        Ok(())
+    }
+
+    pub async fn list_conversations(&self) -> Vec<ConversationSummary> {
+        let lock = self.conversations.read().await;
+        lock.values().map(|c| c.record().into()).collect()
+    }
+
+    pub async fn list_requests(&self) -> Vec<ConversationRequest> {
+        self.requests.read().await.values().cloned().collect()
+    }
+
+    pub async fn accept_request(&self, pk: &PublicKey) -> Result<ConversationSummary> {
+        let request = self.requests.write().await.remove(pk)
+            .ok_or_else(|| GossipError::not_found(format!("no direct conversation request from {pk}")))?;
+        self.broadcast_requests().await?;
+        self.start_conversation(request.pk, request.name, request.peer_node, request.me).await
+    }
+
+    pub async fn decline_request(&self, pk: &PublicKey) -> Result<()> {
+        if self.requests.write().await.remove(pk).is_none() {
+            return Err(GossipError::not_found(format!("no direct conversation request from {pk}")).into());
+        }
+        self.declined.write().await.insert(*pk);
+        self.broadcast_requests().await?;
+        Ok(())
+    }
+
+    pub async fn get_conversation(&self, pk: &PublicKey) -> Result<Conversation> {
+        let lock = self.conversations.read().await;
+        lock.get(pk).cloned().ok_or_else(|| GossipError::not_found(format!("no direct conversation with {pk}")).into())
+    }
+
+    pub async fn start_conversation(&self, pk: PublicKey, name: String, peer_node: WideId, me: PublicKey) -> Result<ConversationSummary> {
+        if let Ok(existing) = self.get_conversation(&pk).await {
+            return Ok(existing.record().into());
+        }
+        // a throwaway identity is discarded with its group, taking the conversation with it
+        if self.identity_service.is_ephemeral(me).await? {
+            return Err(GossipError::permission_denied("direct conversations need a real identity, not an anonymous one").into());
+        }
+        let author = self.identity_service.export_author(me).await?;
+        let record = ConversationRecord {
+            pk,
+            name,
+            doc_id: direct_namespace(&author, pk)?.id().into(),
+            peer_node,
+            me: Some(me)
+        };
+        self.root_doc.write_keyed_blob(direct_key(pk).as_str(), record.clone()).await?;
+
+        let conversation = self.open_conversation(record).await?;
+        conversation.start_sync().await?;
+        self.broadcast_conversations().await?;
+        Ok(conversation.record().into())
+    }
+
+    async fn open_conversation(&self, record: ConversationRecord) -> Result<Conversation> {
+        let me = match record.me {
+            Some(me) => me,
+            None => self.identity_service.get_device_identity_pk().await?
+        };
+        let me = self.identity_service.export_author(me).await?;
+        let pk = record.pk;
+        let conversation = Conversation::open(&self.node, &me, record, self.quarantine.clone()).await?;
+        self.conversations.write().await.insert(pk, conversation.clone());
+        Ok(conversation)
+    }
+
+    async fn broadcast_conversations(&self) -> Result<()> {
+        broadcast(&self.bc, ConversationsUpdated(self.list_conversations().await))?;
+        Ok(())
+    }
+
+    async fn broadcast_requests(&self) -> Result<()> {
+        broadcast(&self.bc, RequestsUpdated(self.list_requests().await))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::Result;
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use iroh::blobs::Hash;
+use iroh::docs::{Author, NamespaceSecret};
+use serde::{Deserialize, Serialize};
+
+use crate::data::{PublicKey, WideId};
+use crate::nearby::model::DisplayMessage;
+use crate::record::Record;
+
+// lives on the root settings doc
+pub const DIRECT_PREFIX: &str = "direct/by_pk";
+// live on the nearby doc
+pub const DIRECT_REQUEST_PREFIX: &str = "direct_request";
+pub const NODE_ID: &str = "node_id";
+
+const DIRECT_NAMESPACE_CONTEXT: &[u8] = b"gossip/direct_namespace/v1";
+
+pub fn direct_key(pk: PublicKey) -> String {
+    format!("{DIRECT_PREFIX}/{pk}")
+}
+
+pub fn direct_request_key(pk: PublicKey) -> String {
+    format!("{DIRECT_REQUEST_PREFIX}/{pk}")
+}
+
+/// Both sides of a conversation can compute the same namespace secret from their
+/// own author secret and the other's public key, so the doc ticket never has to be
+/// sent through the group doc. Only the request (and our node id) is public.
+pub fn direct_namespace(me: &Author, peer: PublicKey) -> Result<NamespaceSecret> {
+    let my_scalar = SigningKey::from_bytes(&me.to_bytes()).to_scalar_bytes();
+    let their_point = VerifyingKey::from_bytes(&peer.to_bytes())?.to_montgomery();
+    let shared = their_point.mul_clamped(my_scalar);
+
+    let mut material = DIRECT_NAMESPACE_CONTEXT.to_vec();
+    material.extend_from_slice(shared.as_bytes());
+    Ok(NamespaceSecret::from_bytes(Hash::new(material).as_bytes()))
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ConversationRecord {
+    pub pk: PublicKey,
+    pub name: String,
+    pub doc_id: WideId,
+    pub peer_node: WideId,
+    // which of our identities this conversation is with, records from before
+    // there was more than one identity are the device identity's
+    #[serde(default)]
+    pub me: Option<PublicKey>
+}
+
+impl Record for ConversationRecord {}
+
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct ConversationSummary {
+    pub pk: PublicKey,
+    pub name: String,
+    pub doc_id: WideId
+}
+
+impl From<&ConversationRecord> for ConversationSummary {
+    fn from(value: &ConversationRecord) -> Self {
+        ConversationSummary {
+            pk: value.pk,
+            name: value.name.clone(),
+            doc_id: value.doc_id
+        }
+    }
+}
+
+/// Someone in the group asked to talk privately. Nothing is opened until it's accepted.
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct ConversationRequest {
+    pub pk: PublicKey,
+    pub name: String,
+    pub peer_node: WideId,
+    // which of our identities they asked
+    pub me: PublicKey
+}
+
+#[derive(Clone, Debug)]
+pub enum DirectServiceEvents {
+    ConversationsUpdated(Vec<ConversationSummary>),
+    RequestsUpdated(Vec<ConversationRequest>)
+}
+
+#[derive(Clone, Debug)]
+pub enum ConversationEvents {
+    AllMessagesUpdated(Vec<DisplayMessage>),
+    ReceivedOneMessage(DisplayMessage)
+}
+
+#[test]
+pub fn both_sides_derive_the_same_namespace() {
+    let alice = Author::new(&mut rand::thread_rng());
+    let bob = Author::new(&mut rand::thread_rng());
+    let from_alice = direct_namespace(&alice, bob.id().into()).unwrap();
+    let from_bob = direct_namespace(&bob, alice.id().into()).unwrap();
+    assert_eq!(from_alice.to_bytes(), from_bob.to_bytes());
+
+    let mallory = Author::new(&mut rand::thread_rng());
+    let from_mallory = direct_namespace(&mallory, bob.id().into()).unwrap();
+    assert_ne!(from_alice.to_bytes(), from_mallory.to_bytes());
+}
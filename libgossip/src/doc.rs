@@ -4,7 +4,7 @@ use std::ops::Deref;
 use std::path::Path;
 use std::sync::Arc;
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use futures_lite::stream::{Stream, StreamExt};
 use iroh::blobs::{BlobFormat, Hash};
 use iroh::blobs::format::collection::Collection;
@@ -18,21 +18,26 @@ use iroh::docs::store::Query;
 use iroh::net::key::PublicKey;
 use iroh::net::{NodeAddr, NodeId};
 use iroh::node::FsNode;
-use serde::{Deserialize, Serialize};
 use tokio::fs::File;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tracing::info;
+use tracing::{debug, info, warn};
 
 use crate::blob_dispatcher::NamedBlob;
 use crate::data::BlobHash;
 use crate::doc::Origin::{Local, Remote};
+use crate::group_key::GroupKey;
+use crate::record;
+use crate::record::Record;
 use crate::nearby::BIO;
+use crate::views::errors::GossipError;
 
 pub type Node = FsNode;
 pub type CoreDoc = iroh::client::docs::Doc;
 
+/// The third field picks which author writes and owns entries on this doc,
+/// falling back to the node's default author when it's None
 #[derive(Clone)]
-pub struct Doc(pub CoreDoc, pub Node);
+pub struct Doc(pub CoreDoc, pub Node, pub Option<AuthorId>);
 
 impl Deref for Doc {
     type Target = CoreDoc;
@@ -52,8 +57,19 @@ pub struct InsertEntry {
 
 impl Doc {
 
+    pub fn new(doc: CoreDoc, node: Node) -> Doc {
+        Doc(doc, node, None)
+    }
+
+    pub fn as_author(&self, author: AuthorId) -> Doc {
+        Doc(self.0.clone(), self.1.clone(), Some(author))
+    }
+
     pub async fn me(&self) -> AuthorId {
-        self.1.authors().default().await.unwrap()
+        match self.2 {
+            Some(author) => author,
+            None => self.1.authors().default().await.unwrap()
+        }
     }
     pub fn blobs(&self) -> &blobs::Client {
         self.1.blobs()
@@ -86,7 +102,7 @@ impl Doc {
     }
 
     pub async fn set_collection(&self, key: &str, blobs: Vec<NamedBlob>) -> Result<()> {
-        let me = self.1.authors().default().await.unwrap();
+        let me = self.me().await;
         let blobs: Vec<(String, Hash)> = blobs.into_iter().map(|i| i.into()).collect();
         let collection: Collection = blobs.into_iter().collect();
         let (blob,_) = self.1.blobs().create_collection(collection, SetTagOption::Auto, vec![]).await?;
@@ -94,7 +110,7 @@ impl Doc {
             self.set_hash(me, String::from(key), blob, size).await?;
             Ok(())
         } else {
-            Err(anyhow!("Cannot set collection if it is not complete"))
+            Err(GossipError::blob_missing("Cannot set collection if it is not complete").into())
         }
     }
 
@@ -111,7 +127,7 @@ impl Doc {
                 mode: Queued
             }).await?;
             let done = downloading.finish().await?;
-            println!("downloaded collection size {}", done.downloaded_size);
+            debug!(collection = %hash, size = done.downloaded_size, "downloaded collection");
             did_do_download = true;
             collection = self.blobs().get_collection(hash.into()).await
         }
@@ -123,7 +139,7 @@ impl Doc {
                 match self.1.blobs().status(i.1).await {
                     Ok(BlobStatus::Complete { .. }) => {}
                     _ => {
-                        println!("tried to access a collection we thought was downloaded, but it wasn't triggering download");
+                        warn!(collection = %hash, "collection we thought was downloaded is missing blobs, downloading it again");
                         // goddamn it, do a whole download
                         let downloading = self.blobs().download_with_opts(hash.into(), DownloadOptions {
                             format: BlobFormat::HashSeq,
@@ -132,7 +148,7 @@ impl Doc {
                             mode: Queued
                         }).await?;
                         let done = downloading.finish().await?;
-                        println!("downloaded collection size {}", done.downloaded_size);
+                        debug!(collection = %hash, size = done.downloaded_size, "downloaded collection");
                         break;
                     }
                 }
@@ -159,6 +175,7 @@ impl Doc {
 
     pub async fn subscribe(&self) -> Result<impl Stream<Item = InsertEntry>> {
         let stream = self.0.subscribe().await?;
+        let id = self.id();
         let mut refmap: HashMap<Hash, InsertEntry> = HashMap::new();
 
         Ok(stream.filter_map(move |e| {
@@ -185,7 +202,7 @@ impl Doc {
                 },
                 Ok(_) => { None },
                 Err(e) => {
-                    println!("some crazy error! {e}");
+                    warn!(doc = %id, "error on doc event stream: {e}");
                     None
                 }
 
@@ -194,6 +211,15 @@ impl Doc {
 
     }
 
+    /// Neighbours coming and going and syncs finishing, the live events subscribe leaves out
+    pub async fn sync_events(&self) -> Result<impl Stream<Item = LiveEvent>> {
+        let stream = self.0.subscribe().await?;
+        Ok(stream.filter_map(|e| match e {
+            Ok(e @ (LiveEvent::NeighborUp(_) | LiveEvent::NeighborDown(_) | LiveEvent::SyncFinished(_))) => Some(e),
+            _ => None
+        }))
+    }
+
     pub async fn list_entries_by_query(&self, query: impl Into<Query>) -> Result<Vec<Entry>> {
         let mut stream = self.0.get_many(query).await?;
         let mut output: Vec<Entry> = vec![];
@@ -203,67 +229,104 @@ impl Doc {
         Ok(output)
     }
 
-    pub async fn read_blobs_by_query<T: for<'a> Deserialize<'a>>(&self, query: impl Into<Query>) -> Result<Vec<T>> {
+    pub async fn read_blobs_by_query<T: Record>(&self, query: impl Into<Query>) -> Result<Vec<T>> {
         let mut stream = self.0.get_many(query).await?;
         let mut output: Vec<T> = vec![];
         while let Some(Ok(entry)) = stream.next().await {
-            output.push(self.read_blob_by_hash(entry.content_hash()).await?);
+            match self.read_blob_by_hash(entry.content_hash()).await {
+                Ok(decoded) => output.push(decoded),
+                Err(e) if record::is_newer_version(&e) => warn!(key = %key_of(&entry), author = %entry.author(), "skipping entry: {e}"),
+                Err(e) => return Err(e)
+            }
         }
         Ok(output)
     }
 
-    pub async fn read_blob_by_hash<T: for<'a> Deserialize<'a>>(&self, hash: Hash) -> Result<T> {
+    pub async fn read_blob_by_hash<T: Record>(&self, hash: Hash) -> Result<T> {
         let bytes = self.1.blobs().read_to_bytes(hash).await?;
-        let r = flexbuffers::Reader::get_root(bytes.iter().as_slice()).unwrap();
-        let decoded = T::deserialize(r).expect("Deserialization failed");
-        Ok(decoded)
+        record::decode(&bytes)
     }
 
-    pub async fn read_any_blob<T: for<'a> Deserialize<'a>>(&self, key: &str) -> Result<Option<T>> {
+    pub async fn read_any_blob<T: Record>(&self, key: &str) -> Result<Option<T>> {
         let hash = self.0.get_one(Query::key_exact(key)).await?;
         self.maybe_read_blob(hash).await
     }
-    pub async fn read_own_blob<T: for<'a> Deserialize<'a>>(&self, key: &str) -> Result<Option<T>> {
-        let author = self.1.authors().default().await.unwrap();
+    pub async fn read_own_blob<T: Record>(&self, key: &str) -> Result<Option<T>> {
+        let author = self.me().await;
         let hash = self.0.get_exact(author, key,false).await?;
         self.maybe_read_blob(hash).await
     }
 
-    async fn maybe_read_blob<T: for<'a> Deserialize<'a>>(&self, hash: Option<Entry>) -> Result<Option<T>> {
+    async fn maybe_read_blob<T: Record>(&self, hash: Option<Entry>) -> Result<Option<T>> {
         match hash {
             None => Ok(None),
-            Some(entry) => {
-                let decoded: T = self.read_blob_by_hash(entry.content_hash()).await?;
-                Ok(Some(decoded))
+            Some(entry) => self.read_entry(&entry).await
+        }
+    }
+
+    /// None, with a warning, for a record written by a newer version than ours
+    pub async fn read_entry<T: Record>(&self, entry: &Entry) -> Result<Option<T>> {
+        match self.read_blob_by_hash(entry.content_hash()).await {
+            Ok(decoded) => Ok(Some(decoded)),
+            Err(e) if record::is_newer_version(&e) => {
+                warn!(key = %key_of(entry), author = %entry.author(), "ignoring entry: {e}");
+                Ok(None)
             }
+            Err(e) => Err(e)
         }
     }
 
-    pub async fn write_blob<T: Serialize>(&self, data: T) -> anyhow::Result<AddOutcome> {
-        let mut s = flexbuffers::FlexbufferSerializer::new();
-        data.serialize(&mut s).expect("Serialization failure");
-        let add = self.1.blobs().add_bytes(s.take_buffer()).await.expect("Persistence failure");
+    pub async fn write_blob<T: Record>(&self, data: T) -> anyhow::Result<AddOutcome> {
+        let bytes = record::encode(&data)?;
+        let add = self.1.blobs().add_bytes(bytes).await?;
         Ok(add)
     }
 
-    pub async fn write_keyed_blob<T: Serialize>(&self, key: &str, data: T) -> anyhow::Result<AddOutcome> {
+    pub async fn write_keyed_blob<T: Record>(&self, key: &str, data: T) -> anyhow::Result<AddOutcome> {
         let add = self.write_blob(data).await?;
-        let author = self.1.authors().default().await.unwrap();
-        self.0.set_hash(author, String::from(key), add.hash, add.size).await.expect("Hash set failure");
+        let author = self.me().await;
+        self.0.set_hash(author, String::from(key), add.hash, add.size).await?;
+        Ok(add)
+    }
+
+    pub async fn read_sealed_blob_by_hash<T: Record>(&self, group_key: &GroupKey, hash: Hash) -> Result<T> {
+        let bytes = self.1.blobs().read_to_bytes(hash).await?;
+        let opened = group_key.open(&bytes)?;
+        record::decode(&opened)
+    }
+
+    pub async fn write_sealed_keyed_blob<T: Record>(&self, group_key: &GroupKey, key: &str, data: T) -> Result<AddOutcome> {
+        let sealed = group_key.seal(&record::encode(&data)?)?;
+        let add = self.1.blobs().add_bytes(sealed).await?;
+        let author = self.me().await;
+        self.0.set_hash(author, String::from(key), add.hash, add.size).await?;
         Ok(add)
     }
 }
 
 
 
+/// The reference file holds the namespace of the root doc, followed by the author
+/// that owns it when that isn't this node's default (ie. after pairing with another device)
 pub async fn create_or_load_from_fs_reference(node: &Node, path: impl AsRef<Path>) -> Doc {
+    let mut author: Option<AuthorId> = None;
     let doc = if path.as_ref().exists() {
         let mut file = File::open(&path).await.expect("Couldn't open existing settings namespace");
-        let mut contents: [u8; 32] = [0; 32];
-        if let Ok(_) = file.read(&mut contents).await {
-            let read_namespace = NamespaceId::from(&contents);
-            info!("Existing settings namespace found, opening: {}", read_namespace);
-            node.docs().open(read_namespace).await.unwrap_or(None)
+        let mut contents: Vec<u8> = vec![];
+        if let Ok(len) = file.read_to_end(&mut contents).await {
+            if len >= 32 {
+                let namespace: [u8; 32] = contents[..32].try_into().unwrap();
+                let read_namespace = NamespaceId::from(&namespace);
+                if len >= 64 {
+                    let paired: [u8; 32] = contents[32..64].try_into().unwrap();
+                    author = Some(AuthorId::from(paired));
+                }
+                info!("Existing settings namespace found, opening: {}", read_namespace);
+                node.docs().open(read_namespace).await.unwrap_or(None)
+            } else {
+                info!("Settings namespace file is too short, recreating");
+                None
+            }
         } else {
             info!("Error reading namespace file contents, recreating");
             None
@@ -271,18 +334,26 @@ pub async fn create_or_load_from_fs_reference(node: &Node, path: impl AsRef<Path
     } else {
         None
     };
-    match doc{
-        Some(doc) => Doc(doc, node.clone()),
-        None => {
+    match (doc, author) {
+        (Some(doc), Some(author)) => Doc::new(doc, node.clone()).as_author(author),
+        (Some(doc), None) => Doc::new(doc, node.clone()),
+        _ => {
             let doc = node.docs().create().await.unwrap();
-            let mut file = File::create(&path).await.expect("couldnt open");
-            let created_ns = doc.id();
-            file.write(created_ns.as_bytes()).await.expect("Failed to save settings doc");
-            Doc(doc, node.clone())
+            write_fs_reference(&path, doc.id(), None).await.expect("Failed to save settings doc");
+            Doc::new(doc, node.clone())
         }
     }
 }
 
+pub async fn write_fs_reference(path: impl AsRef<Path>, namespace: NamespaceId, author: Option<AuthorId>) -> Result<()> {
+    let mut file = File::create(&path).await?;
+    file.write_all(namespace.as_bytes()).await?;
+    if let Some(author) = author {
+        file.write_all(author.as_bytes()).await?;
+    }
+    Ok(())
+}
+
 pub fn key_of(entry: &Entry) -> Cow<'_, str> {
     String::from_utf8_lossy(entry.key())
 }
@@ -1,8 +1,31 @@
-use std::sync::Weak;
+use std::future::Future;
+use std::sync::{OnceLock, Weak};
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
 use async_trait::async_trait;
 use tokio::sync::broadcast;
-use tokio::sync::broadcast::error::SendError;
+use tokio::sync::broadcast::error::{RecvError, SendError};
 use tokio::sync::broadcast::{Receiver, Sender};
+use tracing::{error, warn};
+
+use crate::views::errors::GossipError;
+
+static ERRORS: OnceLock<Sender<GossipError>> = OnceLock::new();
+
+fn error_sender() -> &'static Sender<GossipError> {
+    ERRORS.get_or_init(create_broadcast)
+}
+
+/// Failures in work nobody is waiting on (event handlers, spawned tasks). They
+/// used to panic, now they're logged and handed to whoever is listening
+pub fn report_error(context: &str, e: anyhow::Error) {
+    error!("{context}: {e:?}");
+    let _ = broadcast(error_sender(), GossipError::from(e.context(context.to_string())));
+}
+
+pub fn subscribe_errors() -> Receiver<GossipError> {
+    error_sender().subscribe()
+}
 
 #[async_trait]
 pub trait Starter {
@@ -16,7 +39,7 @@ where
     tokio::spawn(async move {
         let r = o.start().await;
         if let Err(e) = r {
-            eprintln!("{}", e);
+            report_error("failed to start", e);
         }
     });
     starter
@@ -35,6 +58,45 @@ pub fn broadcast<E>(tx: &Sender<E>, event: E) -> Result<usize, SendError<E>> {
     }
 }
 
+/// What a broadcast receiver hands back. The channels only hold a few events, so a
+/// slow subscriber can miss some during a burst (eg. initial sync). When that happens
+/// it gets Resync instead, and should reload whatever it built up from the events.
+pub enum Delivery<E> {
+    Event(E),
+    Resync,
+    Closed
+}
+
+static MISSED_EVENTS: AtomicU64 = AtomicU64::new(0);
+
+/// How many events subscribers have missed by falling behind, since the process started
+pub fn missed_events() -> u64 {
+    MISSED_EVENTS.load(Relaxed)
+}
+
+pub async fn recv_event<E: Clone>(receiver: &mut Receiver<E>) -> Delivery<E> {
+    match receiver.recv().await {
+        Ok(e) => Delivery::Event(e),
+        Err(RecvError::Lagged(missed)) => {
+            warn!(missed, "subscriber fell behind, resyncing");
+            MISSED_EVENTS.fetch_add(missed, Relaxed);
+            Delivery::Resync
+        }
+        Err(RecvError::Closed) => Delivery::Closed
+    }
+}
+
+/// Current state plus everything that changes after it. Subscribing happens before
+/// the snapshot is taken, so nothing in between is missed, at worst it's seen twice.
+pub async fn subscribe_with_snapshot<E, S, F, Fut>(tx: &Sender<E>, snapshot: F) -> anyhow::Result<(S, Receiver<E>)>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = anyhow::Result<S>>
+{
+    let receiver = tx.subscribe();
+    Ok((snapshot().await?, receiver))
+}
+
 pub trait WeakService<I, O> {
     fn get_weak(&self) -> Weak<I>;
     fn from_weak(weak: &Weak<I>) -> Option<O>;
@@ -43,7 +105,7 @@ pub trait WeakService<I, O> {
 
 
 #[async_trait]
-pub trait Subscriber<E, I, O>: WeakService<I, Self> + Sized + Send
+pub trait Subscriber<E, I, O>: WeakService<I, Self> + Sized + Send + Sync
 where
     E: Clone + Sync + Send + 'static,
     I: Send + Sync + 'static
@@ -51,13 +113,26 @@ where
 
      async fn event(&self, event: E) -> anyhow::Result<()>;
 
+     /// Called instead of event when some were missed, override to reload anything
+     /// that depends on seeing all of them
+     async fn resync(&self) -> anyhow::Result<()> {
+         Ok(())
+     }
+
      fn listen_bc(&self, mut receiver: Receiver<E>) {
         let weak = self.get_weak();
         tokio::spawn(async move {
             let weak = weak;
-            while let Ok(e) = receiver.recv().await {
-                if let Some(listener) = Self::from_weak(&weak) {
-                    listener.event(e).await.expect("boom");
+            loop {
+                let delivery = recv_event(&mut receiver).await;
+                let Some(listener) = Self::from_weak(&weak) else { break };
+                let r = match delivery {
+                    Delivery::Event(e) => listener.event(e).await,
+                    Delivery::Resync => listener.resync().await,
+                    Delivery::Closed => break
+                };
+                if let Err(e) = r {
+                    report_error("failed handling event", e);
                 }
             }
         });
@@ -69,7 +144,9 @@ where
             let weak = weak;
             while let Some(e) = receiver.recv().await {
                 if let Some(listener) = Self::from_weak(&weak) {
-                    listener.event(e).await.expect("boom");
+                    if let Err(e) = listener.event(e).await {
+                        report_error("failed handling event", e);
+                    }
                 }
             }
         });
@@ -78,3 +155,23 @@ where
 }
 
 
+
+#[test]
+pub fn lagging_subscribers_are_told_to_resync() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let tx = create_broadcast::<usize>();
+        let mut rx = tx.subscribe();
+        for i in 0..20 {
+            broadcast(&tx, i).unwrap();
+        }
+        assert!(matches!(recv_event(&mut rx).await, Delivery::Resync));
+        // and carries on with what's still buffered
+        assert!(matches!(recv_event(&mut rx).await, Delivery::Event(4)));
+        drop(tx);
+        for _ in 5..20 {
+            recv_event(&mut rx).await;
+        }
+        assert!(matches!(recv_event(&mut rx).await, Delivery::Closed));
+    });
+}
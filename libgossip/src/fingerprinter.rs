@@ -1,110 +1,147 @@
-use std::arch::aarch64::{vadd_u8, vand_u8, vget_lane_u8, vld1_u8, vshl_n_u8, vst1_u8};
-use iroh::docs::{NamespaceId, NamespaceSecret};
-use crate::data::WideId;
+use anyhow::Result;
+use iroh::blobs::Hash;
+
+use crate::data::PublicKey;
+use crate::nearby::model::DocData;
+use crate::views::errors::GossipError;
+
+// the left half of the grid is read from the hash, the right half mirrors it
+const GRID: usize = 16;
+const CELL_PX: usize = 12;
+const MARGIN_PX: usize = CELL_PX;
+const IMAGE_PX: usize = GRID * CELL_PX + MARGIN_PX * 2;
+
+const BACKGROUND: [u8; 3] = [0xF4, 0xF4, 0xF4];
+
+#[derive(uniffi::Record, Debug, Clone, PartialEq)]
+pub struct Fingerprint {
+    /// three groups of five digits, read aloud to compare
+    pub safety_code: String,
+    /// the same grid as `image`, row major, true where a cell is filled
+    pub grid: Vec<bool>,
+    pub grid_size: u32,
+    /// PNG encoded identicon of the grid
+    pub image: Vec<u8>,
+}
+
+#[uniffi::export]
+pub fn fingerprint_public_key(pk: PublicKey) -> Result<Fingerprint, GossipError> {
+    Ok(Fingerprinter::new(pk.to_bytes()).fingerprint()?)
+}
+
+#[uniffi::export]
+pub fn fingerprint_doc(doc_data: DocData) -> Result<Fingerprint, GossipError> {
+    Ok(Fingerprinter::new(doc_data.doc_id.to_bytes()).fingerprint()?)
+}
 
-struct Fingerprinter {
-    hash: [u8; 32],
-    step: [u8; 32]
+pub struct Fingerprinter {
+    hash: [u8; 32]
 }
 
 impl Fingerprinter {
-    pub fn new(hash: [u8; 32]) -> Self {
-        let mut step = [0;32];
-        step[0] = 1;
+    pub fn new(id: [u8; 32]) -> Self {
+        // hash the id so that ids sharing a prefix still look nothing alike
         Fingerprinter {
-            hash,
-            step
+            hash: *Hash::new(id).as_bytes()
         }
     }
-    pub fn reset(&mut self) {
-        self.step = [0; 32];
-        self.step[0] = 1;
-    }
-
-    pub fn iterate(&mut self) -> bool {
-        let output = and_bytes(&self.step, &self.hash);
-        // println!("{:?}", output);
-        // println!("{:?}", self.step);
-        shift_bytes(&mut self.step);
 
-        let any_up = or_bytes(&output);
-
-        // println!("{:?}", any_up);
-        any_up
+    fn bit(&self, index: usize) -> bool {
+        (self.hash[index / 8] >> (index % 8)) & 1 == 1
     }
 
-
-}
-
-fn and_bytes(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
-    let mut result = [0u8; 32];
-
-    for i in 0..32 {
-        result[i] = a[i] & b[i];
+    pub fn grid(&self) -> Vec<bool> {
+        let half = GRID / 2;
+        let mut grid = vec![false; GRID * GRID];
+        for row in 0..GRID {
+            for col in 0..half {
+                let filled = self.bit(row * half + col);
+                grid[row * GRID + col] = filled;
+                grid[row * GRID + (GRID - 1 - col)] = filled;
+            }
+        }
+        grid
     }
 
-    result
-}
-
-fn shift_bytes(arr: &mut [u8; 32]){
-    let shift_amount = 1;
-    assert!(shift_amount < 8, "Shift amount must be less than 8");
-
-    if shift_amount == 0 {
-        return; // No shift needed
+    pub fn safety_code(&self) -> String {
+        // use a separate hash so the code isn't just the grid bits re-read
+        let code_hash = Hash::new([b"gossip/safety_code".as_slice(), self.hash.as_slice()].concat());
+        let bytes = code_hash.as_bytes();
+        bytes.chunks(5).take(3)
+            .map(|chunk| {
+                let n = chunk.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+                format!("{:05}", n % 100_000)
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
     }
 
-    let mut carry = 0u8;
-
-    for i in 0..32 {
-        // Calculate new byte with the shifted value
-        let new_byte = (arr[i] << shift_amount) | carry;
+    fn foreground(&self) -> [u8; 3] {
+        // keep every channel in the middle of the range so it contrasts with the background
+        let [r, g, b] = [self.hash[29], self.hash[30], self.hash[31]];
+        [r % 160 + 24, g % 160 + 24, b % 160 + 24]
+    }
 
-        // Update carry: bits that overflow from the current byte
-        carry = if new_byte < arr[i] { 1u8 } else { 0u8 };
+    pub fn render_png(&self) -> Result<Vec<u8>> {
+        let grid = self.grid();
+        let fg = self.foreground();
+        let mut pixels = Vec::with_capacity(IMAGE_PX * IMAGE_PX * 3);
+        for y in 0..IMAGE_PX {
+            for x in 0..IMAGE_PX {
+                let in_grid = (MARGIN_PX..MARGIN_PX + GRID * CELL_PX).contains(&x)
+                    && (MARGIN_PX..MARGIN_PX + GRID * CELL_PX).contains(&y);
+                let filled = in_grid && grid[((y - MARGIN_PX) / CELL_PX) * GRID + (x - MARGIN_PX) / CELL_PX];
+                pixels.extend_from_slice(if filled { &fg } else { &BACKGROUND });
+            }
+        }
 
-        // Set the shifted value in the array
-        arr[i] = new_byte;
+        let mut out: Vec<u8> = vec![];
+        {
+            let mut encoder = png::Encoder::new(&mut out, IMAGE_PX as u32, IMAGE_PX as u32);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(&pixels)?;
+        }
+        Ok(out)
     }
-}
 
-fn or_bytes(a: &[u8; 32]) -> bool {
-    for i in 0..32 {
-        if a[i] > 0 {
-            return true;
-        }
+    pub fn fingerprint(&self) -> Result<Fingerprint> {
+        Ok(Fingerprint {
+            safety_code: self.safety_code(),
+            grid: self.grid(),
+            grid_size: GRID as u32,
+            image: self.render_png()?,
+        })
     }
-    return false;
 }
 
-
-
 #[test]
-pub fn testy() {
-    for i in (0..100) {
-        let n = NamespaceSecret::new(&mut rand::thread_rng());
-        let w: WideId = n.to_bytes().into();
-        let mut f = Fingerprinter::new(w.into());
-        for i in (0..256) {
-            if i % 16 == 0 {
-                println!("")
-            }
-
-            if f.iterate() {
-                print!("⬛️")
-            } else {
-                print!("⬜️")
-            }
-        }
-        println!("");
-        println!("");
-        println!("");
-        println!("");
-        println!("");
-        println!("");
-        println!("");
-        println!("");
+pub fn fixed_key_fingerprint_is_stable() {
+    let f = Fingerprinter::new([7u8; 32]);
+    let code = f.safety_code();
+    let groups: Vec<&str> = code.split(' ').collect();
+    assert_eq!(groups.len(), 3);
+    assert!(groups.iter().all(|g| g.len() == 5 && g.chars().all(|c| c.is_ascii_digit())));
+    // changing this changes every safety code people have already compared
+    assert_eq!(code, "25057 80074 79025");
+
+    let again = Fingerprinter::new([7u8; 32]);
+    assert_eq!(again.safety_code(), code);
+    assert_eq!(again.grid(), f.grid());
+    for row in f.grid().chunks(GRID) {
+        assert!(row.iter().eq(row.iter().rev()), "rows mirror around the middle");
     }
+    assert_ne!(Fingerprinter::new([8u8; 32]).safety_code(), code);
+}
 
-
-}
\ No newline at end of file
+#[test]
+pub fn fingerprints_are_deterministic() {
+    let w: crate::data::WideId = iroh::docs::NamespaceSecret::new(&mut rand::thread_rng()).to_bytes().into();
+    let a = fingerprint_public_key(w).unwrap();
+    let b = fingerprint_doc(DocData { doc_id: w }).unwrap();
+    assert_eq!(a, b);
+    assert_eq!(&a.image[..8], b"\x89PNG\r\n\x1a\n");
+    assert_eq!(a.safety_code.len(), 17);
+    assert_eq!(a.grid.len(), GRID * GRID);
+}
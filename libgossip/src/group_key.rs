@@ -0,0 +1,94 @@
+use anyhow::Result;
+use crypto_secretbox::{Key, Nonce, XSalsa20Poly1305};
+use crypto_secretbox::aead::{Aead, KeyInit};
+use iroh::blobs::Hash;
+use iroh::docs::{Author, Capability};
+use rand::RngCore;
+
+use crate::views::errors::GossipError;
+
+const GROUP_KEY_CONTEXT: &[u8] = b"gossip/group_key/v1";
+const DEVICE_KEY_CONTEXT: &[u8] = b"gossip/device_key/v1";
+const SEALED_VERSION: u8 = 1;
+const NONCE_LEN: usize = 24;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SealError {
+    #[error("sealed blob is too short or malformed")]
+    Malformed,
+    #[error("sealed blob has unsupported version {0}")]
+    UnsupportedVersion(u8),
+    #[error("failed to decrypt sealed blob, wrong group key?")]
+    Decryption,
+    #[error("failed to encrypt blob")]
+    Encryption,
+}
+
+/// Symmetric key shared by everyone holding write access to a doc. It's derived
+/// from the namespace secret so it travels with the doc ticket and never has to
+/// be written to the doc itself.
+#[derive(Clone)]
+pub struct GroupKey([u8; 32]);
+
+impl GroupKey {
+    pub fn from_capability(cap: &Capability) -> Result<GroupKey> {
+        match cap {
+            Capability::Write(secret) => {
+                let mut material = GROUP_KEY_CONTEXT.to_vec();
+                material.extend_from_slice(&secret.to_bytes());
+                Ok(GroupKey(*Hash::new(material).as_bytes()))
+            }
+            Capability::Read(_) => Err(GossipError::permission_denied("cannot derive a group key from a read only capability").into())
+        }
+    }
+
+    /// For things on the root doc only our own devices should read. Paired devices
+    /// all write to it as the same author, so that's what the key comes from.
+    pub fn from_device_author(author: &Author) -> GroupKey {
+        let mut material = DEVICE_KEY_CONTEXT.to_vec();
+        material.extend_from_slice(&author.to_bytes());
+        GroupKey(*Hash::new(material).as_bytes())
+    }
+
+    /// layout is [version][nonce][ciphertext]
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>, SealError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let cipher = XSalsa20Poly1305::new(Key::from_slice(&self.0));
+        let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| SealError::Encryption)?;
+
+        let mut out = Vec::with_capacity(1 + NONCE_LEN + ciphertext.len());
+        out.push(SEALED_VERSION);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&ciphertext);
+        Ok(out)
+    }
+
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, SealError> {
+        if sealed.len() < 1 + NONCE_LEN {
+            return Err(SealError::Malformed);
+        }
+        if sealed[0] != SEALED_VERSION {
+            return Err(SealError::UnsupportedVersion(sealed[0]));
+        }
+        let (nonce, ciphertext) = sealed[1..].split_at(NONCE_LEN);
+
+        let cipher = XSalsa20Poly1305::new(Key::from_slice(&self.0));
+        cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| SealError::Decryption)
+    }
+}
+
+#[test]
+pub fn seal_round_trip() {
+    let secret = iroh::docs::NamespaceSecret::new(&mut rand::thread_rng());
+    let key = GroupKey::from_capability(&Capability::Write(secret)).unwrap();
+    let sealed = key.seal(b"hello group").unwrap();
+    assert_eq!(key.open(&sealed).unwrap(), b"hello group");
+
+    let other = iroh::docs::NamespaceSecret::new(&mut rand::thread_rng());
+    let other_key = GroupKey::from_capability(&Capability::Write(other)).unwrap();
+    assert!(matches!(other_key.open(&sealed), Err(SealError::Decryption)));
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use argon2::Argon2;
+use crypto_secretbox::{Key, Nonce, XSalsa20Poly1305};
+use crypto_secretbox::aead::{Aead, KeyInit};
+use iroh::docs::Author;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::blob_dispatcher::NamedBlob;
+use crate::identity::IdentityService;
+use crate::identity::model::Identity;
+use crate::nearby::model::Status;
+use crate::settings::{SettingsService, StoreValue};
+use crate::views::errors::GossipError;
+
+const BUNDLE_MAGIC: &[u8] = b"GOSSIPID";
+const BUNDLE_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackupError {
+    #[error("not an identity backup, or it's been cut short")]
+    Malformed,
+    #[error("identity backup has unsupported version {0}")]
+    UnsupportedVersion(u8),
+    #[error("couldn't open identity backup, wrong password?")]
+    WrongPassword,
+    #[error("failed to derive a key from the password")]
+    KeyDerivation,
+    #[error("failed to encrypt identity backup")]
+    Encryption,
+}
+
+/// Everything that makes up who we are, enough to carry on as the same person
+/// on another device
+#[derive(Serialize, Deserialize, Debug)]
+pub struct IdentityBundle {
+    pub identity: Identity,
+    pub author: [u8; 32],
+    pub pic: Option<Vec<u8>>,
+    pub bio: Vec<(String, Vec<u8>)>,
+    pub status: Status,
+    pub settings: HashMap<String, StoreValue>,
+}
+
+fn password_key(password: &str, salt: &[u8]) -> Result<[u8; 32], BackupError> {
+    let mut key = [0u8; 32];
+    Argon2::default().hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|_| BackupError::KeyDerivation)?;
+    Ok(key)
+}
+
+/// layout is [magic][version][salt][nonce][ciphertext]
+pub fn seal_bundle(bundle: &IdentityBundle, password: &str) -> Result<Vec<u8>> {
+    let mut s = flexbuffers::FlexbufferSerializer::new();
+    bundle.serialize(&mut s)?;
+
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let key = password_key(password, &salt)?;
+    let cipher = XSalsa20Poly1305::new(Key::from_slice(&key));
+    let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), s.view())
+        .map_err(|_| BackupError::Encryption)?;
+
+    let mut out = BUNDLE_MAGIC.to_vec();
+    out.push(BUNDLE_VERSION);
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+pub fn open_bundle(sealed: &[u8], password: &str) -> Result<IdentityBundle> {
+    let header = BUNDLE_MAGIC.len() + 1;
+    if sealed.len() < header + SALT_LEN + NONCE_LEN || !sealed.starts_with(BUNDLE_MAGIC) {
+        return Err(BackupError::Malformed.into());
+    }
+    let version = sealed[BUNDLE_MAGIC.len()];
+    if version != BUNDLE_VERSION {
+        return Err(BackupError::UnsupportedVersion(version).into());
+    }
+    let (salt, rest) = sealed[header..].split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let key = password_key(password, salt)?;
+    let cipher = XSalsa20Poly1305::new(Key::from_slice(&key));
+    let plaintext = cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| BackupError::WrongPassword)?;
+
+    let r = flexbuffers::Reader::get_root(plaintext.as_slice())?;
+    Ok(IdentityBundle::deserialize(r)?)
+}
+
+/// Bundles up the active identity and this device's settings, encrypted with password
+pub async fn export_identity(identity: &IdentityService, settings: &SettingsService, password: &str) -> Result<Vec<u8>> {
+    let doc = settings.identity_doc().await;
+    let iden = identity.get_active_identity().await?.ok_or_else(|| GossipError::not_ready("no identity to export yet"))?;
+    let author = identity.export_author(iden.pk).await?;
+
+    let pic = match identity.get_pic(iden.pk).await? {
+        Some((hash, _)) => Some(doc.blobs().read_to_bytes(hash.into()).await?.to_vec()),
+        None => None
+    };
+    let mut bio = vec![];
+    for blob in settings.get_bio().await? {
+        bio.push((blob.name, doc.blobs().read_to_bytes(blob.hash.into()).await?.to_vec()));
+    }
+
+    let bundle = IdentityBundle {
+        identity: iden,
+        author: author.to_bytes(),
+        pic,
+        bio,
+        status: settings.get_status().await?,
+        settings: settings.export_store().await?,
+    };
+    seal_bundle(&bundle, password)
+}
+
+/// Restores an exported identity and makes it the active one. If this device already
+/// has an identity of its own, nothing is touched unless replace_existing is set.
+pub async fn import_identity(identity: &IdentityService, settings: &SettingsService, sealed: &[u8], password: &str, replace_existing: bool) -> Result<Identity> {
+    let bundle = open_bundle(sealed, password)?;
+
+    if let Some(existing) = identity.get_active_identity().await? {
+        if existing.pk != bundle.identity.pk && !replace_existing {
+            return Err(GossipError::permission_denied(format!("this device is already {} ({}), confirm replacing it to import {}",
+                existing.name, existing.pk, bundle.identity.name)).into());
+        }
+    }
+
+    let doc = settings.identity_doc().await;
+    doc.authors().import(Author::from_bytes(&bundle.author)).await?;
+    identity.save_identity(&bundle.identity).await?;
+    if let Some(pic) = bundle.pic {
+        identity.set_pic(bundle.identity.pk, pic).await?;
+    }
+
+    let mut bio = vec![];
+    for (name, data) in bundle.bio {
+        let added = doc.blobs().add_bytes(data).await?;
+        bio.push(NamedBlob { name, hash: added.hash.into() });
+    }
+    if !bio.is_empty() {
+        settings.set_bio(bio).await?;
+    }
+    settings.set_status(&bundle.status).await?;
+    settings.import_store(bundle.settings).await?;
+
+    identity.switch_identity(bundle.identity.pk).await
+}
+
+#[test]
+pub fn bundles_only_open_with_the_right_password() {
+    let author = Author::new(&mut rand::thread_rng());
+    let bundle = IdentityBundle {
+        identity: Identity { name: String::from("kevin"), pk: author.id().into() },
+        author: author.to_bytes(),
+        pic: Some(vec![1, 2, 3]),
+        bio: vec![(String::from("bio_text.txt"), b"hello".to_vec())],
+        status: Status { text: String::from("whats up?") },
+        settings: HashMap::from([(String::from("share_nearby_public_bio"), StoreValue::Bool(true))]),
+    };
+    let sealed = seal_bundle(&bundle, "correct horse").unwrap();
+
+    let opened = open_bundle(&sealed, "correct horse").unwrap();
+    assert_eq!(opened.identity, bundle.identity);
+    assert_eq!(opened.author, bundle.author);
+    assert_eq!(opened.bio, bundle.bio);
+
+    let wrong = open_bundle(&sealed, "battery staple").unwrap_err();
+    assert!(matches!(wrong.downcast_ref::<BackupError>(), Some(BackupError::WrongPassword)));
+    assert!(open_bundle(&sealed[..10], "correct horse").is_err());
+}
@@ -4,17 +4,20 @@ use std::sync::Weak;
 use async_trait::async_trait;
 use crate::data::{BlobHash, PublicKey};
 use crate::doc::{Doc, InsertEntry, key_of};
-use crate::identity::model::{ID_PIC, Identity, IDENTITY};
+use crate::identity::model::{ID_PIC, Identity, IDENTITY, SignedIdentity};
 use anyhow::Result;
 use iroh::client::docs::Entry;
 
 use iroh::docs::store::Query;
-use crate::events::WeakService;
+use tracing::{debug, warn};
+use crate::events::{report_error, WeakService};
+use crate::quarantine::Quarantine;
 
 #[async_trait]
 pub trait IdentityDomainResponder: Send + Sync + 'static {
     async fn identities_did_update(&self, added_new: bool) -> Result<()>;
     async fn pics_did_update(&self) -> Result<()>;
+    async fn identity_rejected(&self, author: PublicKey, reason: String) -> Result<()>;
 }
 
 pub struct IdentityDomain<S, I>
@@ -22,6 +25,7 @@ pub struct IdentityDomain<S, I>
     doc: Doc,
     identities: Vec<Identity>,
     pics: HashMap<PublicKey, BlobHash>,
+    quarantine: Quarantine,
     responder: Weak<I>,
     _phantom: PhantomData<S>
 }
@@ -30,12 +34,13 @@ impl<S, I> IdentityDomain<S, I>
 where
     S: IdentityDomainResponder + WeakService<I,S>
 {
-    pub fn new(doc: &Doc, responder: &S) -> Self
+    pub fn new(doc: &Doc, quarantine: Quarantine, responder: &S) -> Self
     {
         IdentityDomain {
             doc: doc.clone(),
             identities: vec![],
             pics: HashMap::new(),
+            quarantine,
             responder: responder.get_weak(),
             _phantom: PhantomData
         }
@@ -49,8 +54,14 @@ where
         self.identities.clear();
         self.pics.clear();
 
-        let loaded_identities: Vec<Identity> = self.doc.read_blobs_by_query(Query::key_exact(IDENTITY)).await?;
-        println!("loading doc {}, got idens {:?}", self.doc.id(), loaded_identities);
+        let identity_entries = self.doc.list_entries_by_query(Query::key_exact(IDENTITY)).await?;
+        let mut loaded_identities: Vec<Identity> = vec![];
+        for entry in identity_entries {
+            if let Some(iden) = self.read_verified_identity(&entry).await? {
+                loaded_identities.push(iden);
+            }
+        }
+        debug!(doc = %self.doc.id(), identities = loaded_identities.len(), "loaded identities");
         let loaded_pics = self.doc.list_entries_by_query(Query::key_exact(ID_PIC)).await?;
         self.identities = loaded_identities;
         loaded_pics.into_iter().for_each(|entry| {
@@ -73,7 +84,7 @@ where
     }
 
     async fn identity_updated(&mut self, updated_iden: Identity) -> Result<()> {
-        println!("IDENTITY UPDATED {}", updated_iden.name);
+        debug!(doc = %self.doc.id(), pk = %updated_iden.pk, "identity updated");
         let mut updated_iden = Some(updated_iden);
         let mut added_new_iden = false;
 
@@ -88,21 +99,50 @@ where
             self.identities.push(updated);
             added_new_iden = true
         }
-        println!("UPDATED IDEN IS NEW {}", added_new_iden);
 
         if let Some(resp) = S::from_weak(&self.responder) {
             tokio::spawn(async move {
-                resp.identities_did_update(added_new_iden).await.expect("shit")
+                if let Err(e) = resp.identities_did_update(added_new_iden).await {
+                    report_error("failed updating identities", e);
+                }
             });
         }
         Ok(())
     }
 
+    async fn read_verified_identity(&self, entry: &Entry) -> Result<Option<Identity>> {
+        let signed: SignedIdentity = match self.doc.read_blob_by_hash(entry.content_hash()).await {
+            Ok(signed) => signed,
+            Err(e) => {
+                self.quarantine.add(self.doc.id().into(), entry, &e).await;
+                return Ok(None);
+            }
+        };
+        let author: PublicKey = entry.author().into();
+        match signed.verify(author) {
+            Ok(()) => Ok(Some(signed.identity)),
+            Err(e) => {
+                warn!(doc = %self.doc.id(), author = %author, "rejecting identity: {e}");
+                if let Some(resp) = S::from_weak(&self.responder) {
+                    let reason = e.to_string();
+                    tokio::spawn(async move {
+                        if let Err(e) = resp.identity_rejected(author, reason).await {
+                            report_error("failed rejecting identity", e);
+                        }
+                    });
+                }
+                Ok(None)
+            }
+        }
+    }
+
     async fn id_pic_updated(&mut self, entry: Entry) -> Result<()> {
         self.pics.insert(entry.author().into(), entry.content_hash().into());
         if let Some(resp) = S::from_weak(&self.responder) {
             tokio::spawn(async move {
-                resp.pics_did_update().await.expect("shit")
+                if let Err(e) = resp.pics_did_update().await {
+                    report_error("failed updating identity pics", e);
+                }
             });
         }
         Ok(())
@@ -116,14 +156,15 @@ where
         let key = key_of(&e.entry);
         match key.as_ref() {
             IDENTITY => {
-                let i: Identity = self.doc.read_blob_by_hash(e.entry.content_hash()).await?;
-                self.identity_updated(i).await?;
+                if let Some(i) = self.read_verified_identity(&e.entry).await? {
+                    self.identity_updated(i).await?;
+                }
             }
             ID_PIC => {
                 self.id_pic_updated(e.entry).await?;
             }
             _ => {
-                eprintln!("unsupported key {}", key)
+                warn!(doc = %self.doc.id(), key = %key, "unsupported key")
             }
         };
         Ok(())
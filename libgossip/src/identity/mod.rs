@@ -5,19 +5,26 @@ use anyhow::Result;
 use futures_util::StreamExt;
 use iroh::client::blobs::AddOutcome;
 use iroh::client::docs::Entry;
+use iroh::docs::Author;
 use iroh::docs::store::Query;
 use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
 
-use crate::data::{BlobHash, PublicKey};
+use crate::data::{BlobHash, PublicKey, WideId};
 use crate::doc::{Doc, key_of, value_after};
-use crate::events::{broadcast, create_broadcast};
-use crate::identity::model::{ID_PIC_PREFIX, Identity, identity_pic_prefix, identity_prefix, IDENTITY_PREFIX, IdentityServiceEvents};
-use crate::identity::model::IdentityServiceEvents::{DefaultIdentityPicUpdated, DefaultIdentityUpdated};
+use crate::doc::Origin::Remote;
+use crate::events::{broadcast, create_broadcast, subscribe_with_snapshot};
+use crate::group_key::GroupKey;
+use crate::identity::model::{ACTIVE_IDENTITY, ephemeral_key, ID_PIC_PREFIX, Identity, identity_pic_prefix, identity_prefix, IDENTITY_PREFIX, IdentityServiceEvents, persona_key, random_anonymous_name, secret_key, SECRET_PREFIX, SignedIdentity};
+use crate::identity::model::IdentityServiceEvents::{ActiveIdentityChanged, DefaultIdentityPicUpdated, DefaultIdentityUpdated, IdentitiesUpdated, IdentityPicUpdated, IdentityUpdated};
+use crate::views::errors::GossipError;
 
 pub use self::Service as IdentityService;
 
 pub mod model;
 pub mod domain;
+pub mod backup;
 
 
 #[derive(Clone)]
@@ -31,13 +38,15 @@ impl Deref for Service {
 }
 pub struct InnerService {
     bc: Sender<IdentityServiceEvents>,
-    doc: Doc
+    doc: Doc,
+    stop: Notify
 }
 impl Service{
     pub fn new(doc: Doc) -> Service {
         let s = Service(Arc::new(InnerService{
             bc: create_broadcast(),
             doc,
+            stop: Notify::new(),
         }));
         let o = s.clone();
         tokio::spawn(async move { o.observe_doc().await });
@@ -49,17 +58,34 @@ impl InnerService {
     pub fn subscribe(&self) -> Receiver<IdentityServiceEvents> {
         self.bc.subscribe()
     }
+
+    pub async fn subscribe_with_snapshot(&self) -> Result<(Vec<Identity>, Receiver<IdentityServiceEvents>)> {
+        subscribe_with_snapshot(&self.bc, || self.list_identities()).await
+    }
     pub async fn observe_doc(&self) -> Result<()> {
         let mut stream = self.doc.subscribe().await?;
-        while let Some(e) = stream.next().await {
+        loop {
+            let e = tokio::select! {
+                e = stream.next() => match e {
+                    Some(e) => e,
+                    None => break
+                },
+                _ = self.stop.notified() => break
+            };
             match key_of(&e.entry) {
-                s if s.starts_with(IDENTITY_PREFIX) => {
-                    let i: Identity = self.doc.read_blob_by_hash(e.entry.content_hash()).await?;
-                    self.identity_inserted(e.entry, i).await?;
-                }
+                s if s.starts_with(IDENTITY_PREFIX) => match self.doc.read_entry::<Identity>(&e.entry).await {
+                    Ok(Some(i)) => self.identity_inserted(e.entry, i).await?,
+                    Ok(None) => {}
+                    Err(err) => warn!(author = %e.entry.author(), "couldn't read changed identity: {err:#}")
+                },
                 s if s.starts_with(ID_PIC_PREFIX) => {
                     self.id_pic_inserted(e.entry).await?;
                 }
+                s if s.starts_with(SECRET_PREFIX) && matches!(e.location, Remote(_)) => {
+                    if let Err(err) = self.secret_inserted(&e.entry).await {
+                        warn!(author = %e.entry.author(), "couldn't take identity secret from a paired device: {err}");
+                    }
+                }
                 _ => {}
             }
         }
@@ -67,13 +93,17 @@ impl InnerService {
         Ok(())
     }
 
+    /// Ends observe_doc
+    pub fn shutdown(&self) {
+        self.stop.notify_one();
+    }
+
     pub async fn identity_inserted(&self, entry: Entry, iden: Identity) -> Result<()> {
         let key = key_of(&entry);
         let value = value_after(IDENTITY_PREFIX, &key);
-
-
-        println!("got new identity {}", value);
-        if iden.pk == self.get_default_identity_pk().await? {
+        debug!(pk = %value, "identity inserted");
+        broadcast(&self.bc, IdentityUpdated(iden.clone()))?;
+        if iden.pk == self.get_active_identity_pk().await? {
             broadcast(&self.bc, DefaultIdentityUpdated(iden))?;
         }
         Ok(())
@@ -83,25 +113,163 @@ impl InnerService {
         let key = key_of(&entry);
         let pk_from_key = value_after(ID_PIC_PREFIX, &key);
 
-        let default = self.get_default_identity_pk().await?;
-        if pk_from_key == default.to_string() {
-            println!("IDSVC {}, its {}, size {}", key, entry.content_hash(), entry.content_len());
+        let active = self.get_active_identity_pk().await?;
+        if let Some(pk) = self.list_identity_pks().await?.into_iter().find(|pk| pk_from_key == pk.to_string()) {
+            broadcast(&self.bc, IdentityPicUpdated(pk, entry.content_hash().into(), entry.content_len()))?;
+        }
+        if pk_from_key == active.to_string() {
+            debug!(pk = %active, pic = %entry.content_hash(), size = entry.content_len(), "active identity pic updated");
             broadcast(&self.bc, DefaultIdentityPicUpdated(entry.content_hash().into(), entry.content_len()))?;
         }
         Ok(())
     }
 
-    // use default identity thing here
-    pub async fn get_default_identity_pk(&self) -> Result<PublicKey> {
-        let author = self.doc.authors().default().await?;
-        Ok(author.into())
+    /// The author that owns everything on the root settings doc, the node's default
+    /// unless we've paired with another device. Also the identity we fall back to
+    /// when nothing else was picked.
+    pub async fn get_device_identity_pk(&self) -> Result<PublicKey> {
+        Ok(self.doc.me().await.into())
+    }
+
+    async fn device_key(&self) -> Result<GroupKey> {
+        let author = self.export_author(self.get_device_identity_pk().await?).await?;
+        Ok(GroupKey::from_device_author(&author))
     }
 
-    pub async fn get_default_identity(&self) -> Result<Option<Identity>> {
-        let pk = self.get_default_identity_pk().await?;
+    /// Put every identity's secret on the root doc, sealed so only our paired devices can use it
+    pub async fn share_identity_secrets(&self) -> Result<()> {
+        let key = self.device_key().await?;
+        let device = self.get_device_identity_pk().await?;
+        for pk in self.list_identity_pks().await? {
+            if pk == device || self.is_ephemeral(pk).await? {
+                continue;
+            }
+            let author = self.export_author(pk).await?;
+            self.doc.write_sealed_keyed_blob(&key, secret_key(pk).as_str(), author.to_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    async fn secret_inserted(&self, entry: &Entry) -> Result<()> {
+        let key = self.device_key().await?;
+        let secret: [u8; 32] = self.doc.read_sealed_blob_by_hash(&key, entry.content_hash()).await?;
+        let author = Author::from_bytes(&secret);
+        info!(pk = %author.id(), "got identity from a paired device");
+        self.doc.authors().import(author).await?;
+        broadcast(&self.bc, IdentitiesUpdated(self.list_identities().await?))?;
+        Ok(())
+    }
+
+    /// The identity used for anything new, unless a group has its own persona
+    pub async fn get_active_identity_pk(&self) -> Result<PublicKey> {
+        match self.doc.read_own_blob::<WideId>(ACTIVE_IDENTITY).await? {
+            Some(pk) => Ok(pk),
+            None => self.get_device_identity_pk().await
+        }
+    }
+
+    pub async fn get_active_identity(&self) -> Result<Option<Identity>> {
+        let pk = self.get_active_identity_pk().await?;
         self.get_identity(pk).await
     }
 
+    async fn list_identity_pks(&self) -> Result<Vec<PublicKey>> {
+        let mut stream = self.doc.authors().list().await?;
+        let mut pks = vec![];
+        while let Some(author) = stream.next().await {
+            pks.push(author?.into());
+        }
+        Ok(pks)
+    }
+
+    pub async fn list_identities(&self) -> Result<Vec<Identity>> {
+        let mut identities = vec![];
+        for pk in self.list_identity_pks().await? {
+            if self.is_ephemeral(pk).await? {
+                continue;
+            }
+            if let Some(iden) = self.get_identity(pk).await? {
+                identities.push(iden);
+            }
+        }
+        Ok(identities)
+    }
+
+    pub async fn create_identity(&self, name: String) -> Result<Identity> {
+        let author = self.doc.authors().create().await?;
+        let iden = Identity { name, pk: author.into() };
+        self.save_identity(&iden).await?;
+        self.share_identity_secrets().await?;
+        broadcast(&self.bc, IdentitiesUpdated(self.list_identities().await?))?;
+        Ok(iden)
+    }
+
+    /// A fresh author with a random name and nothing else, for dropping into a
+    /// group without tying it to any of our real identities
+    pub async fn create_ephemeral_identity(&self) -> Result<Identity> {
+        let author = self.doc.authors().create().await?;
+        let iden = Identity { name: random_anonymous_name(), pk: author.into() };
+        self.doc.write_keyed_blob(ephemeral_key(iden.pk).as_str(), true).await?;
+        self.save_identity(&iden).await?;
+        Ok(iden)
+    }
+
+    pub async fn is_ephemeral(&self, pk: PublicKey) -> Result<bool> {
+        Ok(self.doc.read_own_blob::<bool>(ephemeral_key(pk).as_str()).await?.unwrap_or(false))
+    }
+
+    /// Forget a throwaway identity for good, including its secret
+    pub async fn discard_ephemeral_identity(&self, pk: PublicKey) -> Result<()> {
+        if !self.is_ephemeral(pk).await? {
+            return Err(GossipError::invalid_input(format!("{pk} is not an ephemeral identity")).into());
+        }
+        let me = self.doc.me().await;
+        self.doc.del(me, identity_prefix(pk)).await?;
+        self.doc.del(me, identity_pic_prefix(pk)).await?;
+        self.doc.del(me, ephemeral_key(pk)).await?;
+        self.doc.authors().delete(pk.into()).await?;
+        debug!(%pk, "discarded ephemeral identity");
+        Ok(())
+    }
+
+    pub async fn switch_identity(&self, pk: PublicKey) -> Result<Identity> {
+        if self.is_ephemeral(pk).await? {
+            return Err(GossipError::invalid_input("ephemeral identities only live in the group they were made for").into());
+        }
+        let iden = self.get_identity(pk).await?.ok_or_else(|| GossipError::not_found(format!("no identity {pk}")))?;
+        self.doc.write_keyed_blob(ACTIVE_IDENTITY, pk).await?;
+        broadcast(&self.bc, ActiveIdentityChanged(iden.clone()))?;
+        Ok(iden)
+    }
+
+    pub async fn delete_identity(&self, pk: PublicKey) -> Result<()> {
+        if pk == self.get_device_identity_pk().await? {
+            return Err(GossipError::permission_denied("the device identity can't be deleted").into());
+        }
+        if pk == self.get_active_identity_pk().await? {
+            return Err(GossipError::invalid_input("switch to another identity before deleting this one").into());
+        }
+        let me = self.doc.me().await;
+        self.doc.del(me, identity_prefix(pk)).await?;
+        self.doc.del(me, identity_pic_prefix(pk)).await?;
+        self.doc.authors().delete(pk.into()).await?;
+        broadcast(&self.bc, IdentitiesUpdated(self.list_identities().await?))?;
+        Ok(())
+    }
+
+    /// The identity we show up as on a particular group doc, if we've been there before
+    pub async fn get_persona_for_doc(&self, doc_id: WideId) -> Result<Option<PublicKey>> {
+        match self.doc.read_own_blob::<WideId>(persona_key(doc_id).as_str()).await? {
+            Some(pk) if self.get_identity(pk).await?.is_some() => Ok(Some(pk)),
+            _ => Ok(None)
+        }
+    }
+
+    pub async fn set_persona_for_doc(&self, doc_id: WideId, pk: PublicKey) -> Result<()> {
+        self.doc.write_keyed_blob(persona_key(doc_id).as_str(), pk).await?;
+        Ok(())
+    }
+
     pub async fn get_identity(&self, pk: PublicKey) -> Result<Option<Identity>> {
         self.doc.read_own_blob(identity_prefix(pk).as_str()).await
     }
@@ -115,19 +283,29 @@ impl InnerService {
         }
     }
 
+    pub async fn export_author(&self, pk: PublicKey) -> Result<Author> {
+        self.doc.authors().export(pk.into()).await?
+            .ok_or_else(|| GossipError::not_found(format!("no author secret for identity {}", pk)).into())
+    }
+
+    pub async fn sign_identity(&self, iden: &Identity) -> Result<SignedIdentity> {
+        let author = self.export_author(iden.pk).await?;
+        let pic = self.get_pic(iden.pk).await?.map(|(hash, _)| hash);
+        Ok(SignedIdentity::sign(iden.clone(), pic, &author))
+    }
+
     pub async fn save_identity(&self, iden: &Identity) -> Result<AddOutcome> {
         let id = iden.pk;
-        let blob = self.doc.write_keyed_blob(identity_prefix(id).as_str(), iden).await?;
+        let blob = self.doc.write_keyed_blob(identity_prefix(id).as_str(), iden.clone()).await?;
         Ok(blob)
     }
 
     pub async fn set_pic(&self, pk: PublicKey, data: Vec<u8>) -> Result<BlobHash> {
-        let author = self.doc.authors().default().await?;
+        let author = self.doc.me().await;
         let add_res = self.doc.blobs().add_bytes(data).await?;
         self.doc.set_hash(author, identity_pic_prefix(pk), add_res.hash, add_res.size).await?;
 
-        let bh: BlobHash = add_res.hash.into();
-        println!("I set add_res.hash as {}, its bh is {}", add_res.hash, bh);
-        Ok(bh)
+        debug!(%pk, pic = %add_res.hash, "set identity pic");
+        Ok(add_res.hash.into())
     }
 }
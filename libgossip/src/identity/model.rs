@@ -1,11 +1,22 @@
+use ed25519_dalek::Signature;
+use iroh::docs::{Author, AuthorId};
+use rand::Rng;
+use rand::seq::SliceRandom;
 use serde::{Deserialize, Serialize};
 use crate::data::{BlobHash, PublicKey, WideId};
+use crate::record::Record;
 
 pub const IDENTITY: &str = "identity";
 pub const ID_PIC: &str = "id_pic";
 
 pub const IDENTITY_PREFIX: &str = "identity/by_pk";
 pub const ID_PIC_PREFIX: &str = "identity/pic/by_pk";
+pub const ACTIVE_IDENTITY: &str = "identity/active";
+pub const PERSONA_PREFIX: &str = "identity/persona/by_doc";
+// marks an identity as a throwaway, see create_ephemeral_identity
+pub const EPHEMERAL_PREFIX: &str = "identity/ephemeral/by_pk";
+// sealed author secrets, so identities made on one paired device work on the others
+pub const SECRET_PREFIX: &str = "identity/secret/by_pk";
 pub fn identity_prefix(pk: WideId) -> String {
     format!("{IDENTITY_PREFIX}/{pk}")
 }
@@ -13,6 +24,36 @@ pub fn identity_prefix(pk: WideId) -> String {
 pub fn identity_pic_prefix(pk: WideId) -> String {
     format!("{ID_PIC_PREFIX}/{pk}")
 }
+
+pub fn persona_key(doc_id: WideId) -> String {
+    format!("{PERSONA_PREFIX}/{doc_id}")
+}
+
+pub fn secret_key(pk: WideId) -> String {
+    format!("{SECRET_PREFIX}/{pk}")
+}
+
+pub fn ephemeral_key(pk: WideId) -> String {
+    format!("{EPHEMERAL_PREFIX}/{pk}")
+}
+
+const ANONYMOUS_ADJECTIVES: [&str; 12] = [
+    "Quiet", "Brisk", "Hidden", "Lucky", "Sleepy", "Curious",
+    "Gentle", "Swift", "Misty", "Bold", "Clever", "Wandering"
+];
+const ANONYMOUS_ANIMALS: [&str; 12] = [
+    "Otter", "Heron", "Fox", "Badger", "Moth", "Lynx",
+    "Finch", "Newt", "Marten", "Owl", "Hare", "Wren"
+];
+
+/// Something friendlier than a pk to call a throwaway identity, with
+/// nothing in it that could tie it back to us
+pub fn random_anonymous_name() -> String {
+    let mut rng = rand::thread_rng();
+    let adjective = ANONYMOUS_ADJECTIVES.choose(&mut rng).unwrap();
+    let animal = ANONYMOUS_ANIMALS.choose(&mut rng).unwrap();
+    format!("{adjective} {animal} {}", rng.gen_range(10..100))
+}
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 #[derive(uniffi::Record)]
 pub struct Identity {
@@ -20,8 +61,81 @@ pub struct Identity {
     pub(crate) pk: PublicKey
 }
 
+impl Record for Identity {}
+
 #[derive(Clone, Debug)]
 pub enum IdentityServiceEvents {
     DefaultIdentityUpdated(Identity),
-    DefaultIdentityPicUpdated(BlobHash, u64)
-}
\ No newline at end of file
+    DefaultIdentityPicUpdated(BlobHash, u64),
+    // same as above but for any of our identities, active or not
+    IdentityUpdated(Identity),
+    IdentityPicUpdated(PublicKey, BlobHash, u64),
+    ActiveIdentityChanged(Identity),
+    IdentitiesUpdated(Vec<Identity>)
+}
+
+const IDENTITY_CLAIM_CONTEXT: &[u8] = b"gossip/identity_claim/v1";
+
+#[derive(Debug, thiserror::Error)]
+pub enum IdentityClaimError {
+    #[error("identity claims pk {claimed} but entry was written by {author}")]
+    AuthorMismatch { claimed: PublicKey, author: PublicKey },
+    #[error("identity claim for {0} has an invalid public key")]
+    InvalidKey(PublicKey),
+    #[error("identity claim for {0} has a bad signature")]
+    BadSignature(PublicKey),
+}
+
+/// What actually gets written under IDENTITY on shared docs. The signature binds
+/// the name and pic to the pk so the claim can be checked in any group, not just
+/// the one it was found in.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SignedIdentity {
+    pub(crate) identity: Identity,
+    pub(crate) pic: Option<BlobHash>,
+    pub(crate) signature: Vec<u8>
+}
+
+impl Record for SignedIdentity {}
+
+impl SignedIdentity {
+    pub fn sign(identity: Identity, pic: Option<BlobHash>, author: &Author) -> Self {
+        let signature = author.sign(&claim_bytes(&identity, &pic)).to_bytes().to_vec();
+        SignedIdentity { identity, pic, signature }
+    }
+
+    pub fn verify(&self, author: PublicKey) -> Result<(), IdentityClaimError> {
+        let claimed = self.identity.pk;
+        if claimed != author {
+            return Err(IdentityClaimError::AuthorMismatch { claimed, author });
+        }
+        let author_id: AuthorId = claimed.into();
+        let public_key = author_id.into_public_key().map_err(|_| IdentityClaimError::InvalidKey(claimed))?;
+        let signature = Signature::from_slice(&self.signature).map_err(|_| IdentityClaimError::BadSignature(claimed))?;
+        public_key.verify(&claim_bytes(&self.identity, &self.pic), &signature)
+            .map_err(|_| IdentityClaimError::BadSignature(claimed))
+    }
+}
+
+fn claim_bytes(identity: &Identity, pic: &Option<BlobHash>) -> Vec<u8> {
+    let mut bytes = IDENTITY_CLAIM_CONTEXT.to_vec();
+    bytes.extend_from_slice(&identity.pk.to_bytes());
+    bytes.extend_from_slice(&pic.map(|p| p.to_bytes()).unwrap_or([0; 32]));
+    bytes.extend_from_slice(identity.name.as_bytes());
+    bytes
+}
+
+#[test]
+pub fn identity_claims_verify() {
+    let author = Author::new(&mut rand::thread_rng());
+    let pk: PublicKey = author.id().into();
+    let signed = SignedIdentity::sign(Identity { name: String::from("kevin"), pk }, None, &author);
+    assert!(signed.verify(pk).is_ok());
+
+    let mut forged = signed.clone();
+    forged.identity.name = String::from("not kevin");
+    assert!(matches!(forged.verify(pk), Err(IdentityClaimError::BadSignature(_))));
+
+    let impostor: PublicKey = Author::new(&mut rand::thread_rng()).id().into();
+    assert!(matches!(signed.verify(impostor), Err(IdentityClaimError::AuthorMismatch { .. })));
+}
@@ -2,25 +2,35 @@ use std::fs;
 use std::path::Path;
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
-use std::sync::atomic::Ordering::Relaxed;
+use std::sync::atomic::Ordering::{Relaxed, SeqCst};
+use std::time::Duration;
 
 use futures_lite::StreamExt;
 use tokio::runtime::Runtime;
 use tokio::sync::RwLock;
-use tracing::info;
-use tracing_subscriber::EnvFilter;
+use tokio::time::timeout;
+use tracing::{debug, info, warn};
 
 use crate::blob_dispatcher::BlobDataDispatcher;
+use crate::contacts::ContactsService;
 use crate::data::{BlobHash, PublicKey};
 use crate::device::DeviceApiServiceProvider;
+use crate::direct::DirectService;
 use crate::doc::{create_or_load_from_fs_reference, Node};
-use crate::identity::IdentityService;
+use crate::events::report_error;
+use crate::identity::{backup, IdentityService};
+use crate::identity::model::Identity;
+use crate::lifecycle::{AppState, Lifecycle};
 use crate::nearby::NearbyService;
+use crate::nearby::timeline::{Timeline, TIMELINE_FILE};
+use crate::quarantine::{Quarantine, QuarantinedEntry};
 use crate::settings::{Service as SettingsService, settings_file_path};
 use crate::views::{Global, GlobalViewModel};
 use crate::views::errors::GossipError;
+use crate::views::direct::{DirectConversationViewController, DirectConversationViewModel};
 use crate::views::nearby_details::{NearbyDetailsViewController, NearbyDetailsViewModel};
 use crate::views::node_stat::{NodeStat, NodeStatViewModel};
+use crate::views::timeline::{ConnectionTimelineViewController, ConnectionTimelineViewModel};
 
 mod data;
 mod device;
@@ -33,10 +43,23 @@ mod events;
 mod nearby;
 mod blob_dispatcher;
 mod fingerprinter;
+mod group_key;
 mod invite;
+mod contacts;
+mod direct;
+mod pairing;
+mod record;
+mod quarantine;
+mod logging;
+mod diagnostics;
+mod lifecycle;
+#[cfg(feature = "metrics")]
+mod metrics;
 
 uniffi::setup_scaffolding!();
 
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[derive(uniffi::Record)]
 pub struct AppConfig {
     pub data_path: String,
@@ -49,7 +72,7 @@ impl AppConfig {
         AppConfig {
             data_path,
             log_directive: None,
-            dev_api: Arc::new(device::DummyApiServiceProvider {})
+            dev_api: Arc::new(device::DummyApiServiceProvider::new())
         }
     }
 }
@@ -60,16 +83,26 @@ pub struct AppHost {
     node: RwLock<Option<Node>>,
     config: AppConfig,
     reset_flag: AtomicBool,
+    shut_down: AtomicBool,
     // services
     settings: SettingsService,
     identity: IdentityService,
-    nearby: NearbyService
+    contacts: ContactsService,
+    nearby: NearbyService,
+    direct: DirectService,
+    quarantine: Quarantine,
+    timeline: Timeline,
+    lifecycle: Lifecycle,
+    /// Ends the accept loop of every MetricsServer started, they hold on to the node
+    #[cfg(feature = "metrics")]
+    metrics_stops: std::sync::Mutex<Vec<Arc<tokio::sync::Notify>>>
 }
 
 impl AppHost {
-    pub fn node(&self) -> Node {
-        let lock = self.node.try_read().unwrap();
-        lock.as_ref().expect("Couldn't borrow node").clone()
+    /// NotReady once shutdown has taken it
+    pub fn node(&self) -> Result<Node, GossipError> {
+        let lock = self.node.try_read().map_err(|_| GossipError::not_ready("the node is shutting down"))?;
+        lock.as_ref().cloned().ok_or_else(|| GossipError::not_ready("the node has been shut down"))
     }
 }
 
@@ -77,17 +110,8 @@ impl AppHost {
 impl AppHost {
     #[uniffi::constructor]
     pub fn new(config: AppConfig) -> AppHost {
-        println!("Started libgossip app");
-        let directive: &str = config.log_directive.as_ref().map_or("ghostlib=debug", |s| s);
-
-        let filter = EnvFilter::from_default_env()
-            .add_directive(directive.parse().unwrap());
-
-        tracing_subscriber::fmt()
-            .compact()
-            .with_env_filter(filter)
-            .with_ansi(false)
-            .init();
+        logging::init(&config.data_path, config.log_directive.as_deref());
+        info!("Started libgossip app");
 
         let rt = Runtime::new().expect("Unable to start a tokio runtime");
         let h = rt.handle().clone();
@@ -95,21 +119,41 @@ impl AppHost {
         let ah = h.block_on(async move {
             let node = Node::persistent(Path::new(&config.data_path.as_str())).await.unwrap().spawn().await.unwrap();
             let root_doc = create_or_load_from_fs_reference(&node, settings_file_path(&config.data_path)).await;
+            if !root_doc.get_peer_nodes().await.is_empty() {
+                // we've been paired with another of our devices before, keep in sync with it.
+                // it may just be out of reach, enter_foreground tries again
+                if let Err(e) = root_doc.start_sync_with_known_peers().await {
+                    report_error("syncing root doc with paired devices", e);
+                }
+            }
 
-            println!("default AUTHOR {}", node.authors().default().await.expect(""));
-            let settings = SettingsService::new(root_doc);
+            info!(author = %node.authors().default().await.expect(""), "default author");
+            let settings = SettingsService::new(root_doc).await;
             let identity = IdentityService::new(settings.identity_doc().await.clone());
-            let nearby = NearbyService::new(node.clone(), identity.clone(), settings.clone(), config.dev_api.clone());
+            let contacts = ContactsService::new(settings.identity_doc().await.clone());
+            let quarantine = Quarantine::new();
+            let timeline = Timeline::new(Path::new(&config.data_path).join(TIMELINE_FILE));
+            let lifecycle = Lifecycle::new();
+            let nearby = NearbyService::new(node.clone(), identity.clone(), settings.clone(), contacts.clone(), quarantine.clone(), timeline.clone(), lifecycle.clone(), config.dev_api.clone());
+            let direct = DirectService::new(node.clone(), settings.identity_doc().await.clone(), identity.clone(), nearby.clone(), quarantine.clone());
 
             AppHost {
                 rt,
                 node: RwLock::new(Some(node)),
                 config,
                 reset_flag: AtomicBool::new(false),
+                shut_down: AtomicBool::new(false),
 
                 settings,
                 identity,
-                nearby
+                contacts,
+                nearby,
+                direct,
+                quarantine,
+                timeline,
+                lifecycle,
+                #[cfg(feature = "metrics")]
+                metrics_stops: std::sync::Mutex::new(vec![])
             }
         });
         ah
@@ -117,75 +161,203 @@ impl AppHost {
 
     pub fn global(&self, view_model: Arc<dyn GlobalViewModel>) -> Arc<Global> {
         let _g = self.rt.enter();
-        Global::new(view_model, self.identity.clone(), self.nearby.clone(), self.settings.clone())
+        Global::new(view_model, self.identity.clone(), self.nearby.clone(), self.settings.clone(), self.contacts.clone(), self.direct.clone())
+    }
+
+    pub fn direct_conversation(&self, view_model: Arc<dyn DirectConversationViewModel>, subject_pk: PublicKey) -> Arc<DirectConversationViewController> {
+        let _g = self.rt.enter();
+        DirectConversationViewController::new(subject_pk, view_model, self.direct.clone())
     }
 
     pub fn nearby_details(&self, view_model: Arc<dyn NearbyDetailsViewModel>, subject_pk: PublicKey) -> Arc<NearbyDetailsViewController> {
         let _g = self.rt.enter();
-        NearbyDetailsViewController::new(subject_pk, view_model, self.identity.clone(), self.nearby.clone(), self.settings.clone())
+        NearbyDetailsViewController::new(subject_pk, view_model, self.nearby.clone(), self.settings.clone())
+    }
+
+    pub fn connection_timeline(&self, view_model: Arc<dyn ConnectionTimelineViewModel>) -> Arc<ConnectionTimelineViewController> {
+        let _g = self.rt.enter();
+        ConnectionTimelineViewController::new(view_model, self.timeline.clone())
     }
 
     pub async fn load_blob(&self, hash: BlobHash) -> Result<Vec<u8>, GossipError> {
-        let n = self.node();
+        let n = self.node()?;
         let mut data = n.blobs().read(hash.into()).await?;
         let bytes = data.read_to_bytes().await?;
         Ok(bytes.into())
     }
 
     pub async fn save_blob(&self, data: Vec<u8>) -> Result<BlobHash, GossipError> {
-        let n = self.node();
+        let n = self.node()?;
         let outcome = n.blobs().add_bytes(data).await?;
         Ok(outcome.hash.into())
     }
 
-    pub fn blobs(&self) -> Arc<BlobDataDispatcher> {
+    pub async fn list_identities(&self) -> Result<Vec<Identity>, GossipError> {
+        Ok(self.identity.list_identities().await?)
+    }
+
+    pub async fn create_identity(&self, name: String) -> Result<Identity, GossipError> {
+        Ok(self.identity.create_identity(name).await?)
+    }
+
+    /// New groups will use this identity, groups we're already in keep theirs
+    pub async fn switch_identity(&self, pk: PublicKey) -> Result<(), GossipError> {
+        self.identity.switch_identity(pk).await?;
+        Ok(())
+    }
+
+    pub async fn delete_identity(&self, pk: PublicKey) -> Result<(), GossipError> {
+        self.identity.delete_identity(pk).await?;
+        Ok(())
+    }
+
+    /// Password protected backup of the active identity, for moving to a new device
+    pub async fn export_identity(&self, password: String) -> Result<Vec<u8>, GossipError> {
+        Ok(backup::export_identity(&self.identity, &self.settings, &password).await?)
+    }
+
+    /// Fails if this device already has its own identity, unless replace_existing is set
+    pub async fn import_identity(&self, bundle: Vec<u8>, password: String, replace_existing: bool) -> Result<Identity, GossipError> {
+        Ok(backup::import_identity(&self.identity, &self.settings, &bundle, &password, replace_existing).await?)
+    }
+
+    /// Show this on one device and give it to pair_with_device on another
+    pub async fn create_pairing_ticket(&self) -> Result<String, GossipError> {
+        let root_doc = self.settings.identity_doc().await;
+        Ok(pairing::create_pairing_ticket(root_doc, &self.identity).await?)
+    }
+
+    /// Takes effect the next time the AppHost is started
+    pub async fn pair_with_device(&self, ticket: String) -> Result<(), GossipError> {
+        Ok(pairing::pair_with_device(&self.node()?, settings_file_path(&self.config.data_path), &ticket).await?)
+    }
+
+    /// Entries from peers that couldn't be decoded since the app started, oldest first
+    pub async fn quarantined_entries(&self) -> Vec<QuarantinedEntry> {
+        self.quarantine.list().await
+    }
+
+    /// Takes the same syntax as RUST_LOG, eg. "libgossip=trace,iroh=info"
+    pub fn set_log_directive(&self, directive: String) -> Result<(), GossipError> {
+        Ok(logging::set_directive(&directive)?)
+    }
+
+    /// The end of the log files under data_path/logs, for sending along with a bug report
+    pub fn export_logs(&self, max_bytes: u64) -> Result<String, GossipError> {
+        Ok(logging::recent_logs(max_bytes)?)
+    }
+
+    /// A collection with diagnostics.json (node, nearby, docs and settings state)
+    /// and the recent log, to send to whoever is looking into a problem
+    pub async fn create_diagnostics_bundle(&self) -> Result<BlobHash, GossipError> {
+        Ok(diagnostics::create_bundle(&self.node()?, &self.settings, &self.nearby, &self.quarantine, &self.timeline).await?)
+    }
+
+    pub fn blobs(&self) -> Result<Arc<BlobDataDispatcher>, GossipError> {
         let _g = self.rt.enter();
-        Arc::new(BlobDataDispatcher::new(self.node()))
+        Ok(Arc::new(BlobDataDispatcher::new(self.node()?)))
     }
 
-    pub fn node_stats(&self, view_model: Arc<dyn NodeStatViewModel>) -> Arc<NodeStat> {
+    pub fn node_stats(&self, view_model: Arc<dyn NodeStatViewModel>) -> Result<Arc<NodeStat>, GossipError> {
         let _g = self.rt.enter();
-        Arc::new(NodeStat::new(view_model, self.node()))
+        Ok(Arc::new(NodeStat::new(view_model, self.node()?, self.lifecycle.clone())))
     }
 
+    /// Logs every connection the node has, at debug
     pub fn print_stats(&self) {
-        let node = self.node();
         let x = self.rt.block_on(async {
-            // let x = node.stats().await?;
-            // println!("wtf is {:?}", x);
+            let node = self.node()?;
             let mut cnx = node.connections().await?;
             while let Some(Ok(c)) = cnx.next().await {
-                println!("nodeinfo {:?}", c);
-                let ci = node.connection_info(c.node_id).await?;
-                if let Some(ci) = ci {
-                    println!("wtf is this {:?}", ci);
+                debug!(peer = %c.node_id, conn_type = %c.conn_type, "connection");
+                if let Some(ci) = node.connection_info(c.node_id).await? {
+                    debug!(peer = %c.node_id, "connection info {ci:?}");
                 }
             }
             Ok::<(),anyhow::Error>(())
         });
         if let Err(e) = x {
-            println!("ERR {}", e);
+            warn!("couldn't list connections: {e:#}");
+        }
+    }
+
+
+    /// Call when the app is suspended. BLE broadcasting and NodeStat polling
+    /// pause and the connection state is checked less often.
+    pub async fn enter_background(&self) {
+        if self.lifecycle.set(AppState::Background) {
+            info!("entering background");
+            self.nearby.enter_background();
         }
     }
 
+    /// Undoes enter_background, then syncs and re-evaluates peers since the
+    /// connections we had may not have survived the suspension
+    pub async fn enter_foreground(&self) -> Result<(), GossipError> {
+        if self.lifecycle.set(AppState::Foreground) {
+            info!("entering foreground");
+            let root_doc = self.settings.identity_doc().await;
+            if !root_doc.get_peer_nodes().await.is_empty() {
+                if let Err(e) = root_doc.start_sync_with_known_peers().await {
+                    report_error("syncing root doc with paired devices", e);
+                }
+            }
+            self.nearby.enter_foreground().await?;
+        }
+        Ok(())
+    }
 
     pub fn set_reset_flag(&self) {
         self.reset_flag.store(true, Relaxed);
     }
 
-    pub fn shutdown(&self) {
-        let lock_ref = &self.node;
+    /// Stops the services' background work, closes their docs and then shuts the
+    /// node down. Calling it again does nothing. Services that haven't stopped
+    /// within SHUTDOWN_TIMEOUT are left behind so the node still goes down.
+    pub fn shutdown(&self) -> Result<(), GossipError> {
+        if self.shut_down.swap(true, SeqCst) {
+            return Ok(());
+        }
         self.rt.block_on(async {
-            // todo shutdown other shit first
+            if timeout(SHUTDOWN_TIMEOUT, self.stop_services()).await.is_err() {
+                warn!("services didn't stop within {SHUTDOWN_TIMEOUT:?}, shutting the node down anyway");
+            }
 
-            let mut lock = lock_ref.write().await;
-            let node: Node = lock.take().unwrap();
-            node.shutdown().await.expect("Node shutdown failed");
-        });
+            let node = self.node.write().await.take();
+            if let Some(node) = node {
+                timeout(SHUTDOWN_TIMEOUT, node.shutdown()).await
+                    .map_err(|_| GossipError::Internal { reason: format!("node didn't shut down within {SHUTDOWN_TIMEOUT:?}") })??;
+            }
+            Ok::<(), GossipError>(())
+        })?;
 
         if self.reset_flag.load(Relaxed) {
             info!("Deleting all data");
-            fs::remove_dir_all(self.config.data_path.as_str()).unwrap();
+            fs::remove_dir_all(self.config.data_path.as_str())
+                .map_err(|e| GossipError::Internal { reason: format!("couldn't delete data: {e}") })?;
+        }
+        Ok(())
+    }
+}
+
+impl AppHost {
+    // the dependents first, so nothing is left using a doc that's been closed
+    async fn stop_services(&self) {
+        if let Err(e) = self.nearby.shutdown().await {
+            warn!("failed to stop nearby: {e:#}");
+        }
+        if let Err(e) = self.direct.shutdown().await {
+            warn!("failed to stop direct conversations: {e:#}");
+        }
+        self.contacts.shutdown();
+        self.identity.shutdown();
+        self.settings.shutdown();
+        if let Err(e) = self.settings.identity_doc().await.close().await {
+            warn!("failed to close the root doc: {e:#}");
+        }
+        #[cfg(feature = "metrics")]
+        for stop in self.metrics_stops.lock().unwrap().drain(..) {
+            stop.notify_one();
         }
     }
 }
@@ -199,35 +371,53 @@ mod tests {
     use async_trait::async_trait;
     use futures_util::StreamExt;
     use tokio::runtime::Runtime;
-    use tokio::sync::Mutex;
+    use tokio::sync::{Mutex, watch};
 
     use crate::{AppConfig, AppHost};
+    use crate::contacts::model::Contact;
     use crate::data::{BlobHash, WideId};
+    use crate::device::DummyApiServiceProvider;
+    use crate::diagnostics::{DIAGNOSTICS_JSON, DIAGNOSTICS_LOG};
+    use crate::direct::model::{ConversationRequest, ConversationSummary};
+    use crate::identity::model::Identity;
+    use crate::nearby::NearbyServiceEvents;
     use crate::nearby::model::{ConState, DisplayMessage, DocData, NearbyProfile, Status};
-    use crate::settings::SHARE_NEARBY_PUBLIC_BIO;
+    use crate::settings::{SettingsEvent, SHARE_NEARBY_PUBLIC_BIO, StoreValue};
+    use crate::views::errors::GossipError;
     use crate::views::GlobalViewModel;
+    use crate::views::node_stat::{NodeStatsData, NodeStatViewModel};
 
     const TEST_DIR: &str = "./testtmp";
-    fn wipe_test_dir(dir: Option<&str>) {
-        let dir: &str = dir.unwrap_or_else(|| TEST_DIR);
-        if let Ok(md) = fs::metadata(dir) {
-            if md.is_dir() {
-                fs::remove_dir_all(dir).unwrap();
-            }
+    /// Empty and only used by the named test, so tests can run in parallel
+    fn fresh_test_dir(name: &str) -> String {
+        let dir = format!("{TEST_DIR}/{name}");
+        if fs::metadata(&dir).is_ok() {
+            fs::remove_dir_all(&dir).unwrap();
         }
+        fs::create_dir_all(&dir).unwrap();
+        dir
     }
 
     #[test]
     fn will_delete_all_data() {
-        wipe_test_dir(None);
-
-        fs::create_dir(TEST_DIR).unwrap();
-        let ah = AppHost::new(AppConfig::new(TEST_DIR.into()) );
+        let dir = fresh_test_dir("will_delete_all_data");
+        let ah = AppHost::new(AppConfig::new(dir.clone()));
         ah.set_reset_flag();
-        ah.shutdown();
+        ah.shutdown().unwrap();
+
+        assert!(fs::metadata(&dir).is_err())
 
-        assert!(matches!(fs::metadata(TEST_DIR), Err(_)))
+    }
 
+    #[test]
+    fn shutdown_can_be_called_twice() {
+        let dir = fresh_test_dir("shutdown_can_be_called_twice");
+        let ah = AppHost::new(AppConfig::new(dir.clone()));
+        ah.shutdown().unwrap();
+        ah.shutdown().unwrap();
+        assert!(ah.node.try_read().unwrap().is_none());
+        assert!(matches!(ah.node(), Err(GossipError::NotReady { .. })));
+        assert!(matches!(ah.blobs(), Err(GossipError::NotReady { .. })));
     }
 
     struct DummyVm(Mutex<Option<tokio::sync::oneshot::Sender<String>>>);
@@ -273,20 +463,42 @@ mod tests {
         }
 
         async fn broadcasting_updated(&self, broadcasting: bool) {
-            todo!()
+
         }
 
         async fn connection_state_updated(&self, state: ConState) {
-            todo!()
+
+        }
+
+        async fn contacts_updated(&self, _contacts: Vec<Contact>) {
+
+        }
+
+        async fn contact_nearby(&self, _contact: Contact) {
+
+        }
+
+        async fn direct_conversations_updated(&self, _conversations: Vec<ConversationSummary>) {
+
+        }
+
+        async fn direct_conversation_requests_updated(&self, _requests: Vec<ConversationRequest>) {
+
+        }
+
+        async fn identities_updated(&self, _identities: Vec<Identity>) {
+
+        }
+
+        async fn background_error(&self, _error: GossipError) {
+
         }
     }
 
     #[test]
     fn view_model_works() {
-        wipe_test_dir(None);
-
-        fs::create_dir(TEST_DIR).unwrap();
-        let ah = AppHost::new(AppConfig::new(TEST_DIR.into()) );
+        let dir = fresh_test_dir("view_model_works");
+        let ah = AppHost::new(AppConfig::new(dir.clone()));
         let _c = ah.rt.enter();
 
         let (tx, rx) = tokio::sync::oneshot::channel();
@@ -305,19 +517,171 @@ mod tests {
 
     #[test]
     fn settings() {
-        wipe_test_dir(None);
-
-        fs::create_dir(TEST_DIR).unwrap();
-        let ah = AppHost::new(AppConfig::new(TEST_DIR.into()) );
+        let dir = fresh_test_dir("settings");
+        let ah = AppHost::new(AppConfig::new(dir.clone()));
         ah.rt.block_on(async {
             // ah.settings.bullshit().await.unwrap();
-            let value = ah.settings.get_setting_defaulted(SHARE_NEARBY_PUBLIC_BIO, || false).await.unwrap();
-            ah.settings.set_setting(SHARE_NEARBY_PUBLIC_BIO, true).await.unwrap();
-            let new_value = ah.settings.get_setting_defaulted(SHARE_NEARBY_PUBLIC_BIO, || false).await.unwrap();
+            let value = ah.settings.get(&SHARE_NEARBY_PUBLIC_BIO).await.unwrap();
+            assert!(!value, "defaults to off");
+            ah.settings.set(&SHARE_NEARBY_PUBLIC_BIO, true).await.unwrap();
+            let new_value = ah.settings.get(&SHARE_NEARBY_PUBLIC_BIO).await.unwrap();
             assert_eq!(new_value, true);
         });
     }
 
+    #[test]
+    fn settings_changes_are_announced() {
+        let dir = fresh_test_dir("settings_changes_are_announced");
+        let ah = AppHost::new(AppConfig::new(dir.clone()));
+        ah.rt.block_on(async {
+            let mut sub = ah.settings.subscribe();
+            ah.settings.set(&SHARE_NEARBY_PUBLIC_BIO, true).await.unwrap();
+            let new = next_change_of(&mut sub, SHARE_NEARBY_PUBLIC_BIO.key).await;
+            assert_eq!(new, StoreValue::Bool(true));
+        });
+    }
+
+    /// Other settings get written while the app starts up, so skip past those
+    async fn next_change_of(sub: &mut tokio::sync::broadcast::Receiver<SettingsEvent>, key: &str) -> StoreValue {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Ok(SettingsEvent::SettingChanged(changed, _, new)) = sub.recv().await {
+                    if changed == key {
+                        return new;
+                    }
+                }
+            }
+        }).await.unwrap()
+    }
+
+    #[test]
+    fn settings_json_round_trips_and_rejects_unknown_keys() {
+        let dir = fresh_test_dir("settings_json_round_trips_and_rejects_unknown_keys");
+        let ah = AppHost::new(AppConfig::new(dir.clone()));
+        ah.rt.block_on(async {
+            ah.settings.set(&SHARE_NEARBY_PUBLIC_BIO, true).await.unwrap();
+            let json = ah.settings.export_json().await.unwrap();
+
+            ah.settings.set(&SHARE_NEARBY_PUBLIC_BIO, false).await.unwrap();
+            ah.settings.import_json(&json).await.unwrap();
+            assert!(ah.settings.get(&SHARE_NEARBY_PUBLIC_BIO).await.unwrap());
+
+            let bad = r#"{"version": 1, "settings": {"no_such_setting": 1, "share_nearby_public_bio": false}}"#;
+            assert!(ah.settings.import_json(bad).await.is_err());
+            assert!(ah.settings.get(&SHARE_NEARBY_PUBLIC_BIO).await.unwrap());
+        });
+    }
+
+    #[test]
+    fn diagnostics_bundle_is_a_collection_with_json_and_logs() {
+        let dir = fresh_test_dir("diagnostics_bundle_is_a_collection_with_json_and_logs");
+        let ah = AppHost::new(AppConfig::new(dir.clone()));
+        ah.rt.block_on(async {
+            let hash = ah.create_diagnostics_bundle().await.unwrap();
+            let collection = ah.node().unwrap().blobs().get_collection(hash.into()).await.unwrap();
+            let names: Vec<&str> = collection.iter().map(|(name, _)| name.as_str()).collect();
+            assert_eq!(names, vec![DIAGNOSTICS_JSON, DIAGNOSTICS_LOG]);
+
+            let (_, json) = collection.iter().next().unwrap();
+            let bytes = ah.load_blob((*json).into()).await.unwrap();
+            let diagnostics: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            assert!(diagnostics["settings"]["settings"].get(SHARE_NEARBY_PUBLIC_BIO.key).is_some());
+            assert!(diagnostics["docs"].as_array().is_some_and(|docs| !docs.is_empty()));
+        });
+    }
+
+    #[test]
+    fn background_pauses_ble_broadcast_until_foreground() {
+        let (ah, device) = host_with_dummy_device("background_pauses_ble_broadcast_until_foreground");
+        ah.rt.block_on(async {
+            ah.nearby.start_scanning().await.unwrap();
+            assert!(device.is_scanning());
+            assert!(device.is_broadcasting());
+
+            ah.enter_background().await;
+            assert!(!device.is_broadcasting());
+            // asking again doesn't start it while we're suspended
+            ah.nearby.update_ble_broadcast(true).await.unwrap();
+            assert!(!device.is_broadcasting());
+
+            ah.enter_foreground().await.unwrap();
+            assert!(device.is_broadcasting());
+            assert!(ah.nearby.should_broadcast().await);
+        });
+        ah.shutdown().unwrap();
+    }
+
+    #[test]
+    fn foreground_rechecks_connection_state_straight_away() {
+        let (ah, _device) = host_with_dummy_device("foreground_rechecks_connection_state_straight_away");
+        ah.rt.block_on(async {
+            ah.nearby.start_scanning().await.unwrap();
+            assert_eq!(ah.nearby.get_con_state().await, ConState::Searching);
+
+            ah.enter_background().await;
+            // Offline the next time the loop looks, which in the background is a minute away
+            ah.nearby.update_scanning(false).await.unwrap();
+            ah.nearby.update_ble_broadcast(false).await.unwrap();
+            assert_eq!(ah.nearby.get_con_state().await, ConState::Searching, "going into the background rechecked");
+
+            let mut events = ah.nearby.subscribe();
+            ah.enter_foreground().await.unwrap();
+            tokio::time::timeout(Duration::from_secs(10), async {
+                while !matches!(events.recv().await, Ok(NearbyServiceEvents::ConStateUpdated(ConState::Offline))) {}
+            }).await.expect("coming back to the foreground didn't recheck");
+        });
+        ah.shutdown().unwrap();
+    }
+
+    struct CountingStatsVm(watch::Sender<usize>);
+    #[async_trait]
+    impl NodeStatViewModel for CountingStatsVm {
+        async fn update_stats(&self, _stats: NodeStatsData) {
+            self.0.send_modify(|polls| *polls += 1);
+        }
+    }
+
+    #[test]
+    fn background_pauses_node_stat_polling() {
+        let (ah, _device) = host_with_dummy_device("background_pauses_node_stat_polling");
+        let (tx, mut polls) = watch::channel(0);
+        let stats = ah.node_stats(Arc::new(CountingStatsVm(tx))).unwrap();
+        stats.set_poll_interval(10);
+        ah.rt.block_on(async {
+            tokio::time::timeout(Duration::from_secs(10), polls.wait_for(|n| *n > 0)).await.unwrap().unwrap();
+
+            ah.enter_background().await;
+            let entered = *polls.borrow();
+            // a poll that was already under way still reports, none after it.
+            // polling every 10ms would have reported dozens of times by now
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            let paused_at = *polls.borrow();
+            assert!(paused_at <= entered + 1, "kept polling in the background");
+
+            ah.enter_foreground().await.unwrap();
+            tokio::time::timeout(Duration::from_secs(10), polls.wait_for(|n| *n > paused_at)).await.unwrap().unwrap();
+        });
+        drop(stats);
+        ah.shutdown().unwrap();
+    }
+
+    /// With its group doc loaded and the connection state loop's first look
+    /// taken, so nearby has something to broadcast and the loop is waiting
+    fn host_with_dummy_device(name: &str) -> (AppHost, DummyApiServiceProvider) {
+        let device = DummyApiServiceProvider::new();
+        let mut config = AppConfig::new(fresh_test_dir(name));
+        config.dev_api = Arc::new(device.clone());
+        let ah = AppHost::new(config);
+        ah.rt.block_on(async {
+            tokio::time::timeout(Duration::from_secs(10), async {
+                while ah.nearby.clone_doc().await.is_err() || ah.nearby.get_con_state().await == ConState::Disconnected {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            }).await.unwrap();
+        });
+        (ah, device)
+    }
+
     #[test]
     fn expiry() {
         let rt = Runtime::new().unwrap();
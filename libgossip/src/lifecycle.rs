@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+#[derive(uniffi::Enum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AppState {
+    Foreground,
+    Background
+}
+
+/// Whether the app is on screen. Shared by everything that should do less, or
+/// nothing, while the OS has us suspended.
+#[derive(Clone)]
+pub struct Lifecycle(Arc<watch::Sender<AppState>>);
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        Lifecycle(Arc::new(watch::Sender::new(AppState::Foreground)))
+    }
+
+    /// False if we were already in that state
+    pub fn set(&self, state: AppState) -> bool {
+        self.0.send_if_modified(|current| {
+            let changed = *current != state;
+            *current = state;
+            changed
+        })
+    }
+
+    pub fn is_background(&self) -> bool {
+        *self.0.borrow() == AppState::Background
+    }
+
+    /// Sees every change after it's made, for loops that should react straight away
+    pub fn subscribe(&self) -> watch::Receiver<AppState> {
+        self.0.subscribe()
+    }
+
+    /// Returns straight away unless we're in the background
+    pub async fn foreground(&self) {
+        let mut rx = self.0.subscribe();
+        let _ = rx.wait_for(|s| *s == AppState::Foreground).await;
+    }
+}
@@ -0,0 +1,130 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::Result;
+use tracing::warn;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{EnvFilter, fmt, Registry, reload};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::views::errors::GossipError;
+
+pub const DEFAULT_LOG_DIRECTIVE: &str = "libgossip=debug";
+const LOG_DIR: &str = "logs";
+const LOG_PREFIX: &str = "gossip";
+// a week of daily files
+const MAX_LOG_FILES: usize = 7;
+
+/// The subscriber is process wide, so there's only ever one of these no matter
+/// how many AppHosts get made. The first one decides where the log files go.
+struct Logging {
+    filter: reload::Handle<EnvFilter, Registry>,
+    dir: PathBuf,
+    // dropping it stops the file writer
+    _guard: Option<WorkerGuard>
+}
+
+static LOGGING: OnceLock<Logging> = OnceLock::new();
+
+pub fn log_dir(data_path: &str) -> PathBuf {
+    Path::new(data_path).join(LOG_DIR)
+}
+
+/// Logs to stdout and to a daily file under data_path/logs, since stdout is gone
+/// on iOS. Later calls only change the directive.
+pub fn init(data_path: &str, directive: Option<&str>) {
+    let directive = directive.unwrap_or(DEFAULT_LOG_DIRECTIVE);
+    if LOGGING.get().is_some() {
+        if let Err(e) = set_directive(directive) {
+            warn!("keeping the current log directive: {e}");
+        }
+        return;
+    }
+
+    let filter = filter_for(directive).unwrap_or_else(|e| {
+        eprintln!("bad log directive {directive}, using {DEFAULT_LOG_DIRECTIVE}: {e}");
+        filter_for(DEFAULT_LOG_DIRECTIVE).expect("default log directive")
+    });
+    let (filter, handle) = reload::Layer::new(filter);
+
+    let dir = log_dir(data_path);
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_PREFIX)
+        .filename_suffix("log")
+        .max_log_files(MAX_LOG_FILES)
+        .build(&dir);
+    let (file_layer, guard) = match appender {
+        Ok(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            (Some(fmt::layer().with_ansi(false).with_writer(writer)), Some(guard))
+        }
+        Err(e) => {
+            eprintln!("couldn't open a log file in {}, only logging to stdout: {e}", dir.display());
+            (None, None)
+        }
+    };
+
+    let installed = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().compact().with_ansi(false))
+        .with(file_layer)
+        .try_init();
+    if let Err(e) = installed {
+        eprintln!("another tracing subscriber is already installed: {e}");
+    }
+
+    let _ = LOGGING.set(Logging { filter: handle, dir, _guard: guard });
+}
+
+/// Same syntax as RUST_LOG, eg. "libgossip=trace,iroh=info". RUST_LOG still
+/// applies underneath.
+pub fn set_directive(directive: &str) -> Result<()> {
+    let logging = LOGGING.get().ok_or_else(|| GossipError::not_ready("logging isn't set up yet"))?;
+    let filter = filter_for(directive).map_err(|e| GossipError::invalid_input(format!("log directive {directive}: {e}")))?;
+    logging.filter.reload(filter).map_err(|e| GossipError::Internal { reason: e.to_string() })?;
+    Ok(())
+}
+
+/// The tail of the log files, oldest lines first, at most max_bytes of it
+pub fn recent_logs(max_bytes: u64) -> Result<String> {
+    let logging = LOGGING.get().ok_or_else(|| GossipError::not_ready("logging isn't set up yet"))?;
+    let mut files: Vec<PathBuf> = fs::read_dir(&logging.dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.file_name().is_some_and(|n| n.to_string_lossy().starts_with(LOG_PREFIX)))
+        .collect();
+    // the date suffix sorts them oldest first
+    files.sort();
+
+    let mut chunks: Vec<Vec<u8>> = vec![];
+    let mut remaining = max_bytes as usize;
+    for file in files.iter().rev() {
+        if remaining == 0 {
+            break;
+        }
+        let bytes = fs::read(file)?;
+        let start = bytes.len().saturating_sub(remaining);
+        remaining -= bytes.len() - start;
+        chunks.push(bytes[start..].to_vec());
+    }
+    chunks.reverse();
+
+    Ok(String::from_utf8_lossy(&chunks.concat()).into_owned())
+}
+
+fn filter_for(directive: &str) -> Result<EnvFilter, tracing_subscriber::filter::ParseError> {
+    directive.split(',')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .try_fold(EnvFilter::from_default_env(), |filter, d| Ok(filter.add_directive(d.parse()?)))
+}
+
+#[test]
+pub fn log_directives_are_checked_before_they_are_applied() {
+    assert!(filter_for("libgossip=trace, iroh=info").is_ok());
+    assert!(filter_for("libgossip=loud").is_err());
+}
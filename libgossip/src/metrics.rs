@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use futures_lite::StreamExt;
+use iroh::docs::store::Query;
+use iroh::net::endpoint::ConnectionType;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+use crate::AppHost;
+use crate::doc::{Doc, Node};
+use crate::events::missed_events;
+use crate::nearby::{MESSAGES, NearbyService};
+use crate::nearby::model::ConState;
+use crate::nearby::timeline::{Timeline, TimelineEntry, TimelineEvent};
+use crate::views::errors::GossipError;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUEST_LINE: usize = 8 * 1024;
+
+/// Serves prometheus metrics on /metrics until it's dropped or the AppHost shuts down
+#[derive(uniffi::Object)]
+pub struct MetricsServer {
+    addr: SocketAddr,
+    stop: Arc<Notify>
+}
+
+#[uniffi::export]
+impl MetricsServer {
+    /// Where it ended up listening, handy when asked for port 0
+    pub fn address(&self) -> String {
+        self.addr.to_string()
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop.notify_one();
+    }
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl AppHost {
+    /// eg. "127.0.0.1:0" for a free port, see MetricsServer::address for which
+    pub async fn serve_metrics(&self, addr: String) -> Result<Arc<MetricsServer>, GossipError> {
+        let addr: SocketAddr = addr.parse().map_err(|e| GossipError::invalid_input(format!("metrics address {addr}: {e}")))?;
+        let listener = TcpListener::bind(addr).await.map_err(|e| GossipError::network(format!("couldn't listen on {addr}: {e}")))?;
+        let addr = listener.local_addr().map_err(|e| GossipError::network(e.to_string()))?;
+        let stop = Arc::new(Notify::new());
+        let sources = Sources { node: self.node()?, nearby: self.nearby.clone(), timeline: self.timeline.clone() };
+
+        info!(%addr, "serving metrics");
+        self.metrics_stops.lock().unwrap().push(stop.clone());
+        let stop_clone = stop.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    accepted = listener.accept() => {
+                        match accepted {
+                            Ok((stream, _)) => {
+                                let sources = sources.clone();
+                                tokio::spawn(async move {
+                                    if let Err(e) = sources.respond(stream).await {
+                                        warn!("failed to answer metrics request: {e}");
+                                    }
+                                });
+                            }
+                            Err(e) => warn!("failed to accept metrics connection: {e}")
+                        }
+                    }
+                    _ = stop_clone.notified() => {
+                        break;
+                    }
+                }
+            }
+            info!(%addr, "stopped serving metrics");
+        });
+
+        Ok(Arc::new(MetricsServer { addr, stop }))
+    }
+}
+
+#[derive(Clone)]
+struct Sources {
+    node: Node,
+    nearby: NearbyService,
+    timeline: Timeline
+}
+
+impl Sources {
+    async fn respond(&self, mut stream: TcpStream) -> Result<()> {
+        let request_line = timeout(REQUEST_TIMEOUT, read_request_line(&mut stream)).await
+            .map_err(|_| anyhow!("no request line within {REQUEST_TIMEOUT:?}"))??;
+        let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+
+        let (status, body) = if path == "/metrics" {
+            match self.render().await {
+                Ok(body) => ("200 OK", body),
+                Err(e) => ("500 Internal Server Error", format!("{e:#}\n"))
+            }
+        } else {
+            ("404 Not Found", String::from("try /metrics\n"))
+        };
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+
+    async fn render(&self) -> Result<String> {
+        let mut out = String::new();
+
+        let mut by_type: BTreeMap<&str, f64> = ["direct", "relay", "mixed", "none"].into_iter().map(|t| (t, 0.0)).collect();
+        let mut connections = self.node.connections().await?;
+        while let Some(Ok(info)) = connections.next().await {
+            *by_type.entry(conn_type_label(&info.conn_type)).or_default() += 1.0;
+        }
+        metric(&mut out, "gossip_connections", "gauge", "Connections to other nodes by type",
+               by_type.into_iter().map(|(t, n)| (format!("type=\"{t}\""), n)));
+
+        let mut sync_peers = vec![];
+        let mut posts = vec![];
+        let mut docs = self.node.docs().list().await?;
+        while let Some(item) = docs.next().await {
+            let (id, _) = item?;
+            let Some(core) = self.node.docs().open(id).await? else {
+                continue;
+            };
+            let doc = Doc::new(core, self.node.clone());
+            let label = format!("doc=\"{id}\"");
+            sync_peers.push((label.clone(), doc.get_active_connected_peer_count().await? as f64));
+            posts.push((label, doc.list_entries_by_query(Query::key_prefix(MESSAGES)).await?.len() as f64));
+        }
+        metric(&mut out, "gossip_doc_sync_peers", "gauge", "Peers actively syncing each doc", sync_peers);
+        metric(&mut out, "gossip_doc_posts", "gauge", "Posts in each doc, only group docs have any", posts);
+
+        let mut blob_bytes = 0;
+        let mut blobs = self.node.blobs().list().await?;
+        while let Some(blob) = blobs.next().await {
+            blob_bytes += blob?.size;
+        }
+        metric(&mut out, "gossip_blob_store_bytes", "gauge", "Size of every blob in the store", [(String::new(), blob_bytes as f64)]);
+
+        metric(&mut out, "gossip_events_missed_total", "counter", "Events subscribers missed by falling behind", [(String::new(), missed_events() as f64)]);
+
+        let current = self.nearby.get_con_state().await;
+        metric(&mut out, "gossip_con_state", "gauge", "The connection state we're in now",
+               [(format!("state=\"{}\"", con_state_label(current)), 1.0)]);
+        let mut durations: BTreeMap<&str, f64> = BTreeMap::new();
+        for (state, duration) in con_state_spans(&self.timeline.list().await, SystemTime::now()) {
+            *durations.entry(con_state_label(state)).or_default() += duration.as_secs_f64();
+        }
+        metric(&mut out, "gossip_con_state_seconds", "gauge", "Time spent in each connection state, as far back as the timeline goes",
+               durations.into_iter().map(|(s, secs)| (format!("state=\"{s}\""), secs)));
+
+        Ok(out)
+    }
+}
+
+/// Only the request line matters, nobody sends us a body
+async fn read_request_line(stream: &mut TcpStream) -> Result<String> {
+    let mut buf = vec![];
+    let mut chunk = [0u8; 256];
+    while !buf.windows(2).any(|w| w == b"\r\n") {
+        if buf.len() > MAX_REQUEST_LINE {
+            return Err(anyhow!("request line longer than {MAX_REQUEST_LINE} bytes"));
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let line = buf.split(|b| *b == b'\r').next().unwrap_or_default();
+    Ok(String::from_utf8_lossy(line).into_owned())
+}
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, samples: impl IntoIterator<Item = (String, f64)>) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    for (labels, value) in samples {
+        if labels.is_empty() {
+            let _ = writeln!(out, "{name} {value}");
+        } else {
+            let _ = writeln!(out, "{name}{{{labels}}} {value}");
+        }
+    }
+}
+
+fn conn_type_label(conn_type: &ConnectionType) -> &'static str {
+    match conn_type {
+        ConnectionType::Direct(_) => "direct",
+        ConnectionType::Relay(_) => "relay",
+        ConnectionType::Mixed(_, _) => "mixed",
+        ConnectionType::None => "none"
+    }
+}
+
+fn con_state_label(state: ConState) -> &'static str {
+    match state {
+        ConState::Offline => "offline",
+        ConState::Searching => "searching",
+        ConState::Connected(_) => "connected",
+        ConState::Reconnecting => "reconnecting",
+        ConState::Disconnected => "disconnected",
+        ConState::Invalid => "invalid"
+    }
+}
+
+/// How long each connection state lasted, in order, as far back as the timeline
+/// goes. The last one is the state we're still in.
+fn con_state_spans(entries: &[TimelineEntry], now: SystemTime) -> Vec<(ConState, Duration)> {
+    let mut spans = vec![];
+    let mut current: Option<(ConState, SystemTime)> = None;
+    for entry in entries {
+        if let TimelineEvent::ConStateChanged { to, .. } = entry.event {
+            if let Some((state, since)) = current {
+                spans.push((state, entry.at.duration_since(since).unwrap_or_default()));
+            }
+            current = Some((to, entry.at));
+        }
+    }
+    if let Some((state, since)) = current {
+        spans.push((state, now.duration_since(since).unwrap_or_default()));
+    }
+    spans
+}
+
+#[test]
+pub fn con_state_spans_run_until_the_next_change() {
+    let start = SystemTime::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    let entries = vec![
+        TimelineEntry { at: at(0), event: TimelineEvent::ConStateChanged { from: ConState::Offline, to: ConState::Searching } },
+        TimelineEntry { at: at(5), event: TimelineEvent::DocLoaded { doc: [0u8; 32].into() } },
+        TimelineEntry { at: at(10), event: TimelineEvent::ConStateChanged { from: ConState::Searching, to: ConState::Connected(2) } },
+    ];
+
+    let spans = con_state_spans(&entries, at(70));
+    assert_eq!(spans, vec![(ConState::Searching, Duration::from_secs(10)), (ConState::Connected(2), Duration::from_secs(60))]);
+
+    let mut out = String::new();
+    metric(&mut out, "gossip_test", "gauge", "A test", [(String::from("state=\"connected\""), 60.0)]);
+    assert!(out.ends_with("gossip_test{state=\"connected\"} 60\n"));
+}
+
+#[test]
+pub fn request_line_is_read_across_packets() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = tokio::spawn(async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(b"GET /met").await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            stream.write_all(b"rics HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+            stream
+        });
+        let (mut server, _) = listener.accept().await.unwrap();
+        assert_eq!(read_request_line(&mut server).await.unwrap(), "GET /metrics HTTP/1.1");
+        drop(client.await.unwrap());
+    });
+}
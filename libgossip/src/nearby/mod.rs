@@ -1,17 +1,17 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ops::Deref;
 use std::sync::{Arc, Weak};
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering::Relaxed;
 use std::time::Duration;
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use async_trait::async_trait;
 use futures_lite::StreamExt;
 use iroh::base::node_addr::AddrInfoOptions::Id;
 use iroh::base::ticket;
 use iroh::blobs::Hash;
-use iroh::client::docs::Entry;
+use iroh::client::docs::{Entry, LiveEvent};
 use iroh::client::docs::ShareMode::Write;
 use iroh::docs::{Capability, DocTicket};
 use iroh::docs::store::Query;
@@ -19,35 +19,44 @@ use iroh::net::{NodeAddr, NodeId};
 use tokio::sync::broadcast::{Receiver, Sender};
 use tokio::sync::mpsc::channel;
 use tokio::sync::{Notify, RwLock, RwLockWriteGuard};
+use tracing::{debug, field, info, instrument, Span, warn};
 
 use crate::ble::{AddressData, BLEGossipBroadcaster, BLEGossipScanner, BluetoothPeerEvent, DocumentData, GossipScannerDelegate, PeerData, PeerState};
 use crate::blob_dispatcher::{CollectionState, LoadCollectionDelegate, NamedBlob};
+use crate::contacts::ContactsService;
+use crate::contacts::model::{Contact, ContactsServiceEvents};
 use crate::data::{BlobHash, collection_from_dir, PublicKey, replace_or_add_blob, UUID, WideId};
 use crate::device::DeviceApiServiceProvider;
-use crate::doc::{CoreDoc, Doc, InsertEntry, key_of, Node};
-use crate::events::{broadcast, create_broadcast, Subscriber, WeakService};
+use crate::direct::model::{DIRECT_REQUEST_PREFIX, direct_request_key, NODE_ID};
+use crate::doc::{CoreDoc, Doc, InsertEntry, key_of, Node, value_after};
+use crate::events::{broadcast, create_broadcast, report_error, Subscriber, WeakService};
+use crate::group_key::GroupKey;
 use crate::identity::domain::{IdentityDomain, IdentityDomainResponder};
 use crate::identity::IdentityService;
+use crate::lifecycle::{AppState, Lifecycle};
 use crate::identity::model::{ID_PIC, IDENTITY, Identity, IdentityServiceEvents};
-use crate::nearby::model::{BioDetails, ConState, DocData, display_msg_map, DisplayMessage, NearbyProfile, Post, Status};
+use crate::nearby::model::{BioDetails, BlePeerDiagnostics, ConState, DocData, display_msg_map, DisplayMessage, NearbyDiagnostics, NearbyProfile, Post, Status};
 use crate::nearby::model::ConState::{Connected, Disconnected, Invalid, Offline, Reconnecting, Searching};
-use crate::nearby::NearbyServiceEvents::{AllMessagesUpdated, BioUpdated, ConStateUpdated, DocDataUpdated, IdentitiesUpdated, ReceivedOneNewMessage, BroadcastingUpdated};
+use crate::nearby::NearbyServiceEvents::{AllMessagesUpdated, BioUpdated, ConStateUpdated, ContactNearby, DirectConversationRequested, DocDataUpdated, IdentitiesUpdated, IdentityRejected, ReceivedOneNewMessage, BroadcastingUpdated};
 use crate::nearby::peer_calc::{collect_addrs_for_doc, find_best_doc_from_peers};
 use crate::nearby::post::{PostDomain, PostDomainResponder};
+use crate::nearby::timeline::{Timeline, TimelineEvent};
 use crate::nearby::State::{Ready, Uninitialized};
-use crate::settings::{CURRENT_NEARBY_DOC_ID, SettingsEvent, SettingsService, SHARE_NEARBY_PUBLIC_BIO, StoreValue};
+use crate::quarantine::Quarantine;
+use crate::settings::{ANONYMOUS_IN_NEW_GROUPS, CURRENT_NEARBY_DOC_ID, KEEP_CONNECTION_TIMELINE, SettingsEvent, SettingsService, SHARE_NEARBY_PUBLIC_BIO, StoreValue};
+use crate::views::errors::GossipError;
 
 pub use self::Service as NearbyService;
 
 pub mod model;
 mod peer_calc;
-mod post;
+pub mod post;
+pub mod timeline;
 
 pub const PUBLIC_STATUS: &str = "status";
 pub const MESSAGES: &str = "messages";
 
 pub const BIO: &str = "public_bio";
-pub const MESSAGE_PAYLOADS: &str = "message_payloads";
 
 #[derive(Clone)]
 pub struct Service(Arc<InnerService>);
@@ -66,6 +75,10 @@ pub enum NearbyServiceEvents {
     ReceivedOneNewMessage(DisplayMessage),
     BioUpdated(PublicKey),
     ConStateUpdated(ConState),
+    IdentityRejected(PublicKey, String),
+    ContactNearby(Contact),
+    // pk and name of who's asking, the node to reach them at, and which of our identities they asked
+    DirectConversationRequested(PublicKey, String, WideId, PublicKey),
 }
 
 pub enum State {
@@ -84,6 +97,8 @@ pub enum State {
         should_broadcast: bool,
         ble_peers: HashMap<UUID, PeerData>,
         messages: PostDomain<Service, InnerService>,
+        // contacts we've already raised a friend nearby for on this doc
+        announced_contacts: HashSet<PublicKey>,
     },
 }
 
@@ -93,17 +108,32 @@ pub struct InnerService {
     ble_scanner: Arc<dyn BLEGossipScanner>,
     identity_service: IdentityService,
     settings_service: SettingsService,
+    contacts_service: ContactsService,
+    quarantine: Quarantine,
+    timeline: Timeline,
+    lifecycle: Lifecycle,
+    con_state_stop: Notify,
+    // the identity we use on the current group doc, kept outside of state so
+    // responders called while state is locked can still read it
+    persona: RwLock<Option<PublicKey>>,
     state: RwLock<State>,
 }
 
 impl Service {
-    pub fn new(node: Node, identity_service: IdentityService, settings_service: SettingsService, device: Arc<dyn DeviceApiServiceProvider>) -> Service {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(node: Node, identity_service: IdentityService, settings_service: SettingsService, contacts_service: ContactsService, quarantine: Quarantine, timeline: Timeline, lifecycle: Lifecycle, device: Arc<dyn DeviceApiServiceProvider>) -> Service {
         let s = Service(Arc::new(InnerService {
             bc: create_broadcast(),
             ble_broadcaster: device.ble_broadcaster(),
             ble_scanner: device.ble_scanner(),
             identity_service,
             settings_service,
+            contacts_service,
+            quarantine,
+            timeline,
+            lifecycle,
+            con_state_stop: Notify::new(),
+            persona: RwLock::new(None),
             state: RwLock::new(Uninitialized { node }),
         }));
         let o = s.clone();
@@ -115,7 +145,7 @@ impl Service {
 #[async_trait]
 impl PostDomainResponder for Service {
     async fn all_posts_updated(&self, posts: Vec<Post>) -> Result<()> {
-        let me = self.identity_service.get_default_identity_pk().await?;
+        let me = self.persona().await?;
         let dms: Vec<DisplayMessage> = posts.into_iter().enumerate()
             .map(|(i, m)| { display_msg_map(i, &me, m) })
             .collect();
@@ -124,7 +154,7 @@ impl PostDomainResponder for Service {
     }
 
     async fn one_post_updated(&self, count: usize, post: Post) -> Result<()> {
-        let me = self.identity_service.get_default_identity_pk().await?;
+        let me = self.persona().await?;
         let dm: DisplayMessage = display_msg_map(count - 1, &me, post);
         broadcast(&self.bc, ReceivedOneNewMessage(dm))?;
         Ok(())
@@ -134,7 +164,7 @@ impl PostDomainResponder for Service {
 #[async_trait]
 impl IdentityDomainResponder for Service {
     async fn identities_did_update(&self, added_new: bool) -> Result<()> {
-        let profiles: Vec<NearbyProfile> = {
+        let mut profiles: Vec<NearbyProfile> = {
             let lock = self.state.read().await;
             if let Ready { ref identities, ref statuses, .. } = *lock {
                 let pics = identities.pics();
@@ -145,15 +175,20 @@ impl IdentityDomainResponder for Service {
                         name: i.name,
                         pic: pics.get(&i.pk).copied(),
                         status: statuses.get(&i.pk).cloned().or_else(|| Some(Status { text: String::new() })).unwrap(),
+                        is_contact: false,
                     }
                 }).collect()
             } else {
-                return Err(anyhow!("wtf"));
+                return Err(GossipError::not_ready("no group doc loaded yet").into());
             }
         };
+        for p in profiles.iter_mut() {
+            p.is_contact = self.contacts_service.is_contact(&p.pk).await?;
+        }
         if added_new {
             // no point if we're just changing our name
             self.check_if_found_group().await?;
+            self.announce_contacts_nearby(&profiles).await?;
         }
 
         broadcast(&self.bc, IdentitiesUpdated(profiles))?;
@@ -164,6 +199,11 @@ impl IdentityDomainResponder for Service {
         self.identities_did_update(false).await?;
         Ok(())
     }
+
+    async fn identity_rejected(&self, author: PublicKey, reason: String) -> Result<()> {
+        broadcast(&self.bc, IdentityRejected(author, reason))?;
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -186,6 +226,15 @@ impl Subscriber<SettingsEvent, InnerService, Service> for Service {
 
         Ok(())
     }
+
+    async fn resync(&self) -> Result<()> {
+        let lock = self.state.read().await;
+        if let Ready { ref doc, .. } = *lock {
+            self.update_my_status_on_doc(&self.settings_service.get_status().await?, doc).await?;
+            self.update_my_bio_on_doc(doc).await?;
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -193,27 +242,65 @@ impl Subscriber<IdentityServiceEvents, InnerService, Service> for Service {
     async fn event(&self, event: IdentityServiceEvents) -> Result<()> {
         let lock = self.state.read().await;
         if let Ready { ref doc, .. } = *lock {
+            // only the identity this group knows us by matters here
+            let persona: PublicKey = doc.me().await.into();
             match event {
-                IdentityServiceEvents::DefaultIdentityUpdated(iden) => {
+                IdentityServiceEvents::IdentityUpdated(iden) if iden.pk == persona => {
                     self.update_my_identity_on_doc(&iden, doc).await?;
                 }
-                IdentityServiceEvents::DefaultIdentityPicUpdated(hash, size) => {
+                IdentityServiceEvents::IdentityPicUpdated(pk, hash, size) if pk == persona => {
                     self.update_my_pic_on_doc(hash, size, doc).await?;
+                    // the signed identity claim covers the pic, so it needs re-signing too
+                    if let Some(iden) = self.identity_service.get_identity(persona).await? {
+                        self.update_my_identity_on_doc(&iden, doc).await?;
+                    }
                 }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn resync(&self) -> Result<()> {
+        let lock = self.state.read().await;
+        if let Ready { ref doc, .. } = *lock {
+            if let Some(iden) = self.identity_service.get_identity(doc.me().await.into()).await? {
+                self.update_my_identity_on_doc(&iden, doc).await?;
             }
         }
         Ok(())
     }
 }
 
+#[async_trait]
+impl Subscriber<ContactsServiceEvents, InnerService, Service> for Service {
+    async fn event(&self, event: ContactsServiceEvents) -> Result<()> {
+        match event {
+            ContactsServiceEvents::ContactsUpdated(_) => {
+                // refresh the is_contact flags on everyone in the group
+                self.identities_did_update(false).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn resync(&self) -> Result<()> {
+        self.identities_did_update(false).await
+    }
+}
+
 #[async_trait]
 impl Subscriber<BluetoothPeerEvent, InnerService, Service> for Service {
     async fn event(&self, (uuid, data): BluetoothPeerEvent) -> Result<()> {
         let found_group = {
             let mut lock = self.state.write().await;
             if let Ready { ref mut ble_peers, found_group, .. } = *lock {
-                ble_peers.insert(uuid, data);
-                println!("bluetooth peer found {:?}", uuid);
+                let settled = data.peer_state == PeerState::Settled;
+                if ble_peers.insert(uuid, data.clone()).as_ref() != Some(&data) {
+                    debug!(peer = ?uuid, "bluetooth peer found");
+                    drop(lock);
+                    self.record(TimelineEvent::BlePeerSeen { peer: uuid, settled }).await;
+                }
                 found_group
             } else {
                 true
@@ -243,7 +330,7 @@ impl Service {
 
 
     async fn get_initial_doc(&self, node: &Node) -> Result<CoreDoc> {
-        let previous_nearby: Option<WideId> = self.settings_service.get_setting(CURRENT_NEARBY_DOC_ID).await?;
+        let previous_nearby: Option<WideId> = self.settings_service.get_setting(&CURRENT_NEARBY_DOC_ID).await?;
         let mut doc: Option<CoreDoc> = None;
         if let Some(previous_nearby) = previous_nearby {
             doc = node.docs().open(previous_nearby.into()).await?;
@@ -257,8 +344,8 @@ impl Service {
     async fn initialize(&self) -> Result<()> {
         let mut lock = self.state.write().await;
         if let Uninitialized { ref node } = *lock {
-            let doc = Doc(self.get_initial_doc(node).await?, node.clone());
-            *lock = self.ready_state_with_doc(doc).await;
+            let doc = Doc::new(self.get_initial_doc(node).await?, node.clone());
+            *lock = self.ready_state_with_doc(doc).await?;
             drop(lock);
 
             self.update_ble_broadcast(false).await?;
@@ -281,15 +368,19 @@ impl Service {
             let pics = identities.pics();
             let iden = identities.identities_ref().into_iter().find(|i| &i.pk == pk);
             if let Some(iden) = iden {
-                return Ok(NearbyProfile {
+                let mut profile = NearbyProfile {
                     pk: iden.pk,
                     name: iden.name.clone(),
                     pic: pics.get(pk).copied(),
                     status: statuses.get(pk).cloned().unwrap_or(Status { text: String::default() }),
-                });
+                    is_contact: false,
+                };
+                drop(lock);
+                profile.is_contact = self.contacts_service.is_contact(pk).await?;
+                return Ok(profile);
             }
         }
-        Err(anyhow!("profile does not exist here"))
+        Err(GossipError::not_found("profile does not exist here").into())
     }
 
     pub async fn get_con_state(&self) -> ConState {
@@ -315,12 +406,24 @@ impl Service {
         Ok(())
     }
 
-    async fn ready_state_with_doc(&self, doc: Doc) -> State {
-        let ticket = doc.share(Write, Id).await.expect("generating doc ticket");
-        let messages = PostDomain::new(&doc, self);
-        let identities = IdentityDomain::new(&doc, self);
+    async fn ready_state_with_doc(&self, doc: Doc) -> Result<State> {
+        // pin whoever we joined as, switching the active identity later shouldn't
+        // change who we are in a group we're already in
+        let doc_id: WideId = doc.id().into();
+        let persona = match self.identity_service.get_persona_for_doc(doc_id).await? {
+            Some(pk) => pk,
+            None => self.persona_for_new_group().await?
+        };
+        self.identity_service.set_persona_for_doc(doc_id, persona).await?;
+        *self.persona.write().await = Some(persona);
+        let doc = doc.as_author(persona.into());
+
+        let ticket = doc.share(Write, Id).await?;
+        let group_key = GroupKey::from_capability(&ticket.capability)?;
+        let messages = PostDomain::new(&doc, group_key, self.quarantine.clone(), self);
+        let identities = IdentityDomain::new(&doc, self.quarantine.clone(), self);
 
-        Ready {
+        Ok(Ready {
             doc,
             con_state: ConState::Disconnected,
             doc_stop: Arc::new(Notify::new()),
@@ -332,14 +435,77 @@ impl Service {
             should_scan: false,
             should_broadcast: false,
             messages,
+            announced_contacts: HashSet::new(),
+        })
+    }
+
+    async fn persona_for_new_group(&self) -> Result<PublicKey> {
+        if self.settings_service.get(&ANONYMOUS_IN_NEW_GROUPS).await? {
+            Ok(self.identity_service.create_ephemeral_identity().await?.pk)
+        } else {
+            self.identity_service.get_active_identity_pk().await
+        }
+    }
+
+    /// Throwaway identities only exist for the one group, once we're done with
+    /// it there's nothing left that links back to them
+    async fn retire_persona(&self, persona: PublicKey) -> Result<()> {
+        if self.identity_service.is_ephemeral(persona).await? {
+            self.identity_service.discard_ephemeral_identity(persona).await?;
+        }
+        Ok(())
+    }
+
+    /// Who we are on the current group doc
+    pub async fn persona(&self) -> Result<PublicKey> {
+        self.persona.read().await.ok_or_else(|| GossipError::not_ready("no group doc loaded yet").into())
+    }
+
+    /// Show up as a different one of our identities in the current group. Whatever
+    /// the old persona had put on the doc is removed so the group doesn't see both.
+    pub async fn set_group_persona(&self, pk: PublicKey) -> Result<()> {
+        if self.identity_service.get_identity(pk).await?.is_none() {
+            return Err(GossipError::not_found(format!("no identity {pk}")).into());
+        }
+        let (old, new_doc) = {
+            let lock = self.state.read().await;
+            if let Ready { ref doc, ref doc_stop, .. } = *lock {
+                let old: PublicKey = doc.me().await.into();
+                if old == pk {
+                    return Ok(());
+                }
+                for key in [IDENTITY, PUBLIC_STATUS, ID_PIC, BIO, NODE_ID] {
+                    doc.del(old.into(), key).await?;
+                }
+                self.identity_service.set_persona_for_doc(doc.id().into(), pk).await?;
+                doc_stop.notify_waiters();
+                (old, Doc::new(doc.0.clone(), doc.1.clone()))
+            } else { return Err(GossipError::not_ready("no group doc loaded yet").into()); }
+        };
+        {
+            let mut lock = self.state.write().await;
+            *lock = self.ready_state_with_doc(new_doc).await?;
         }
+        self.retire_persona(old).await?;
+        self.load_doc().await?;
+        Ok(())
+    }
+
+    /// Swap whoever we are in the current group for a brand new throwaway identity
+    pub async fn go_anonymous(&self) -> Result<Identity> {
+        let iden = self.identity_service.create_ephemeral_identity().await?;
+        self.set_group_persona(iden.pk).await?;
+        Ok(iden)
     }
 
     pub async fn start_sync(&self) -> Result<()> {
         let lock = self.state.read().await;
         if let Ready { ref doc, .. } = *lock {
-            println!("syncing with known peers!");
+            info!(doc = %doc.id(), "syncing with known peers");
             doc.start_sync_with_known_peers().await?;
+            let doc_id = doc.id().into();
+            drop(lock);
+            self.record(TimelineEvent::SyncStarted { doc: doc_id }).await;
         }
         Ok(())
     }
@@ -358,6 +524,7 @@ impl Service {
     pub async fn listen_to_other_services(&self) -> Result<()> {
         self.listen_bc(self.identity_service.subscribe());
         self.listen_bc(self.settings_service.subscribe());
+        self.listen_bc(self.contacts_service.subscribe());
         Ok(())
     }
 
@@ -382,7 +549,9 @@ impl Service {
         self.update_scanning(false).await?;
         self.update_ble_broadcast(false).await?;
         let mut state = self.state.write().await;
+        let mut old_persona = None;
         if let Ready { ref doc, ref doc_stop, .. } = *state {
+            old_persona = Some(doc.me().await.into());
             let node = doc.1.clone();
             // doc.leave().await?;
             doc_stop.notify_waiters();
@@ -390,13 +559,16 @@ impl Service {
             let old_doc_to_delete = doc.id();
             // doc.close().await?;
 
-            let doc = Doc(node.docs().create().await?, node.clone());
-            *state = self.ready_state_with_doc(doc).await;
+            let doc = Doc::new(node.docs().create().await?, node.clone());
+            *state = self.ready_state_with_doc(doc).await?;
 
-            println!("note to self, delete {}", old_doc_to_delete);
+            debug!(doc = %old_doc_to_delete, "left doc, it isn't deleted yet");
             // node.docs().drop_doc(old_doc_to_delete).await?;
         };
         drop(state);
+        if let Some(old_persona) = old_persona {
+            self.retire_persona(old_persona).await?;
+        }
         self.load_doc().await?;
         self.calculate_and_update_con_state().await?;
 
@@ -408,15 +580,15 @@ impl Service {
             if *should_scan != new_should_scan {
                 *should_scan = new_should_scan;
                 if *should_scan {
-                    println!("started ble scanning");
+                    info!("started ble scanning");
                     self.ble_scanner.start_scanning();
                 } else {
-                    println!("stopped ble scanning");
+                    info!("stopped ble scanning");
                     self.ble_scanner.stop_scanning();
                 }
             }
         } else {
-            println!("wtf happened here??");
+            warn!("can't change scanning before a group doc is loaded");
         }
 
         Ok(())
@@ -434,11 +606,11 @@ impl Service {
             let my_doc = get_document_data(doc_share);
             let best_new_doc: DocumentData = find_best_doc_from_peers(&my_doc, &ble_peers);
             if best_new_doc == my_doc {
-                println!("best doc is me, i sit here");
+                debug!(doc = %doc.id(), "best doc nearby is ours, staying put");
                 return Ok(());
             }
 
-            println!("lets join a new document!");
+            info!(doc = %doc.id(), "found a better doc nearby, joining it");
             let addrs: Vec<AddressData> = collect_addrs_for_doc(&best_new_doc, ble_peers.values());
 
             let cap: Capability = postcard::from_bytes(&best_new_doc).map_err(ticket::Error::Postcard).expect("boom1");
@@ -452,15 +624,17 @@ impl Service {
             let doc_ticket = DocTicket { capability: cap, nodes: addrs };
             let new_doc = doc.1.docs().import(doc_ticket.clone()).await?;
             doc_stop.notify_waiters(); // we're about to LEAVE THE OLD DOC BEHIND VERY IMPORTANT,
-            Doc(new_doc, doc.1.clone())
+            (doc.me().await.into(), Doc::new(new_doc, doc.1.clone()))
         } else { return Ok(()); };
         drop(lock);
+        let (old_persona, new_doc) = new_doc;
 
         {
             let mut lock = self.state.write().await;
             // this should drop the old doc and all that shit
-            *lock = self.ready_state_with_doc(new_doc).await;
+            *lock = self.ready_state_with_doc(new_doc).await?;
         }
+        self.retire_persona(old_persona).await?;
         self.load_doc().await?;
 
         Ok(())
@@ -478,7 +652,8 @@ impl Service {
             self.ble_broadcaster.set_document_data(document);
             self.ble_broadcaster.set_address_data(addrs);
 
-            if new_should_broadcast {
+            // stays off in the background, enter_foreground turns it back on
+            if new_should_broadcast && !self.lifecycle.is_background() {
                 self.ble_broadcaster.start();
             } else {
                 self.ble_broadcaster.stop();
@@ -491,25 +666,42 @@ impl Service {
 
 
     pub async fn update_my_identity_on_doc(&self, iden: &Identity, doc: &Doc) -> Result<()> {
-        doc.write_keyed_blob(IDENTITY, iden).await?;
+        let signed = self.identity_service.sign_identity(iden).await?;
+        doc.write_keyed_blob(IDENTITY, signed).await?;
         Ok(())
     }
+    /// The status is device wide, so it'd link a throwaway identity back to us
     pub async fn update_my_status_on_doc(&self, status: &Status, doc: &Doc) -> Result<()> {
-        doc.write_keyed_blob(PUBLIC_STATUS, status).await?;
+        if self.identity_service.is_ephemeral(doc.me().await.into()).await? {
+            return Ok(());
+        }
+        doc.write_keyed_blob(PUBLIC_STATUS, status.clone()).await?;
+        Ok(())
+    }
+
+    /// Lets people open a direct conversation with us later on. Not for a throwaway
+    /// identity, it's the same node id we publish next to our real one in other groups.
+    pub async fn update_my_node_id_on_doc(&self, doc: &Doc) -> Result<()> {
+        if self.identity_service.is_ephemeral(doc.me().await.into()).await? {
+            return Ok(());
+        }
+        let node_id: WideId = doc.1.node_id().into();
+        doc.write_keyed_blob(NODE_ID, node_id).await?;
         Ok(())
     }
 
     pub async fn update_my_pic_on_doc(&self, hash: BlobHash, size: u64, doc: &Doc) -> Result<()> {
-        let pk = self.identity_service.get_default_identity_pk().await?;
-        doc.set_hash(pk.into(), ID_PIC, hash.into(), size).await?;
+        doc.set_hash(doc.me().await, ID_PIC, hash.into(), size).await?;
         Ok(())
     }
 
     pub async fn update_my_bio_on_doc(&self, doc: &Doc) -> Result<()> {
-        let should_post_bio = self.settings_service.get_setting_defaulted(SHARE_NEARBY_PUBLIC_BIO, || false).await?;
-        if should_post_bio {
+        // a throwaway identity never carries our bio, that'd defeat the point
+        let anonymous = self.identity_service.is_ephemeral(doc.me().await.into()).await?;
+        let should_post_bio = self.settings_service.get(&SHARE_NEARBY_PUBLIC_BIO).await?;
+        if should_post_bio && !anonymous {
             if let Some(entry) = self.settings_service.get_bio_entry().await? {
-                println!("propagating my bio doc from settings to this nearby doc, i am {}, bio hash is {}", entry.author(), entry.content_hash());
+                debug!(doc = %doc.id(), author = %entry.author(), bio = %entry.content_hash(), "propagating bio to doc");
                 doc.set_hash(doc.me().await, BIO, entry.content_hash(), entry.content_len()).await?;
             }
         } else {
@@ -521,7 +713,7 @@ impl Service {
 
     async fn handle_setting_change(&self, key: &str, _old: Option<StoreValue>, new: StoreValue) -> Result<()> {
         match (key, new) {
-            (SHARE_NEARBY_PUBLIC_BIO, StoreValue::Bool(_)) => {
+            (key, StoreValue::Bool(_)) if key == SHARE_NEARBY_PUBLIC_BIO.key => {
                 let doc = self.clone_doc().await?;
                 self.update_my_bio_on_doc(&doc).await?;
             }
@@ -530,8 +722,8 @@ impl Service {
         Ok(())
     }
     pub async fn put_self_on_doc(&self, doc: &Doc) -> Result<()> {
-        println!("puttin myself on doc {}", doc.0.id());
-        if let Some(iden) = self.identity_service.get_default_identity().await? {
+        debug!(doc = %doc.id(), "putting self on doc");
+        if let Some(iden) = self.identity_service.get_identity(doc.me().await.into()).await? {
             self.update_my_identity_on_doc(&iden, doc).await?;
             let status = self.settings_service.get_status().await?;
             self.update_my_status_on_doc(&status, doc).await?;
@@ -539,10 +731,10 @@ impl Service {
             if let Some((pic_hash, size)) = self.identity_service.get_pic(iden.pk).await? {
                 self.update_my_pic_on_doc(pic_hash, size, doc).await?;
             }
-
+            self.update_my_node_id_on_doc(doc).await?;
             self.update_my_bio_on_doc(&doc).await?;
         } else {
-            println!("Couldn't put self on doc cuz no identity, but will do it when its there!");
+            info!(doc = %doc.id(), "no identity to put on doc yet, will once there is one");
         }
         Ok(())
     }
@@ -605,7 +797,7 @@ impl Service {
 
     pub async fn set_gallery_pics(&self, pics: Vec<BlobHash>) -> Result<()> {
         if pics.len() > 9 {
-            return Err(anyhow!("only supports up to 9 gallery pics"));
+            return Err(GossipError::invalid_input("only supports up to 9 gallery pics").into());
         };
 
         // get rid of old pics, keep non pic stuff
@@ -621,8 +813,8 @@ impl Service {
         Ok(())
     }
 
+    #[instrument(skip_all, fields(doc))]
     pub async fn load_doc(&self) -> Result<()> {
-        println!("load_doc");
         // load existing data
         let outs = {
             let mut lock = self.state.write().await;
@@ -636,9 +828,10 @@ impl Service {
                 ref mut statuses, ..
             } = *lock
             {
+                Span::current().record("doc", field::display(doc.id()));
                 // should i combine this with initialize?
                 identities.set_doc(doc);
-                messages.set_doc(doc);
+                messages.set_doc(doc, GroupKey::from_capability(&doc_share.capability)?);
 
                 // whenever we load a new doc, lets make sure we broadcast it
                 self.ble_broadcaster.set_document_data(get_document_data(doc_share));
@@ -654,13 +847,14 @@ impl Service {
                 let status_entries: Vec<Entry> = doc.list_entries_by_query(Query::key_exact(PUBLIC_STATUS)).await?;
                 *statuses = HashMap::new();
                 for se in status_entries {
-                    let status: Status = doc.read_blob_by_hash(se.content_hash()).await?;
-                    statuses.insert(se.author().into(), status);
+                    match doc.read_blob_by_hash::<Status>(se.content_hash()).await {
+                        Ok(status) => { statuses.insert(se.author().into(), status); }
+                        Err(e) => self.quarantine.add(doc.id().into(), &se, &e).await
+                    }
                 }
-                println!("got here");
                 let id: WideId = doc.id().into();
-                self.settings_service.set_setting(CURRENT_NEARBY_DOC_ID, id).await?;
-                Some((doc.id(), doc.subscribe().await?, doc_stop.clone()))
+                self.settings_service.set(&CURRENT_NEARBY_DOC_ID, id).await?;
+                Some((doc.id(), doc.subscribe().await?, doc.sync_events().await?, doc_stop.clone()))
             } else { None }
         };
         // we may have loaded a doc that we already have identities and a group on
@@ -672,33 +866,44 @@ impl Service {
         self.identities_did_update(false).await?;
         self.broadcast_all_messages().await?;
 
-        println!("about here now");
+        // someone may have asked for a direct conversation before we got here
+        let doc = self.clone_doc().await?;
+        let me = self.persona().await?;
+        for entry in doc.list_entries_by_query(Query::key_exact(direct_request_key(me))).await? {
+            self.direct_request_found(&entry, &doc).await?;
+        }
 
-        let (id, mut stream, doc_stop_clone) = outs.unwrap();
+        let (id, mut stream, mut sync_stream, doc_stop_clone) = outs.unwrap();
+        self.record(TimelineEvent::DocLoaded { doc: id.into() }).await;
 
         let self_clone = self.clone();
 
-        println!("🟢 Starting listening to {id}");
+        info!(doc = %id, "🟢 listening to doc");
         tokio::spawn(async move {
             loop {
                 tokio::select! {
                     Some(e) = stream.next() => {
                         if let Err(e) =  self_clone.handle_insert_entry(e).await {
-                            eprintln!("error handling insert entry {}", e.to_string())
+                            warn!(doc = %id, "error handling insert entry: {e}")
+                        }
+                    }
+                    Some(e) = sync_stream.next() => {
+                        if let Some(event) = sync_timeline_event(id.into(), e) {
+                            self_clone.record(event).await;
                         }
                     }
                     _ = doc_stop_clone.notified() => {
-                        println!("we got notified!");
                         break;
                     }
                 }
             }
-            println!("🛑 Stopping listening to {id}");
+            info!(doc = %id, "🛑 stopped listening to doc");
             Ok::<(), anyhow::Error>(())
         });
         Ok(())
     }
 
+    #[instrument(skip_all, fields(key = %key_of(&e.entry), author = %e.entry.author()))]
     async fn handle_insert_entry(&self, e: InsertEntry) -> Result<()> {
         let mut lock = self.state.write().await;
         if let Ready {
@@ -707,14 +912,23 @@ impl Service {
         {
             match key_of(&e.entry).as_ref() {
                 PUBLIC_STATUS => {
-                    let s: Status = doc.read_blob_by_hash(e.entry.content_hash()).await?;
-                    drop(lock); //IMPORTANT TO DO
-                    self.status_update(e.entry.author().into(), s).await?;
+                    match doc.read_blob_by_hash::<Status>(e.entry.content_hash()).await {
+                        Ok(s) => {
+                            drop(lock); //IMPORTANT TO DO
+                            self.status_update(e.entry.author().into(), s).await?;
+                        }
+                        Err(err) => self.quarantine.add(doc.id().into(), &e.entry, &err).await
+                    }
                 }
                 BIO => {
                     drop(lock);
                     broadcast(&self.bc, BioUpdated(e.entry.author().into()))?;
                 }
+                key if key.starts_with(DIRECT_REQUEST_PREFIX) => {
+                    let doc = doc.clone();
+                    drop(lock);
+                    self.direct_request_found(&e.entry, &doc).await?;
+                }
                 key if identities.handles(key) => {
                     identities.insert_entry(e).await?;
                 }
@@ -727,8 +941,44 @@ impl Service {
         Ok(())
     }
 
+    pub async fn get_node_id(&self, pk: &PublicKey) -> Result<Option<WideId>> {
+        let doc = self.clone_doc().await?;
+        let entry = doc.0.get_exact(pk.into(), NODE_ID, false).await?;
+        match entry {
+            None => Ok(None),
+            Some(e) => Ok(Some(doc.read_blob_by_hash(e.content_hash()).await?))
+        }
+    }
+
+    /// Leaves a note on the group doc asking pk to open a direct conversation with us.
+    /// The conversation doc itself is never mentioned, both sides derive it.
+    /// Refused for a throwaway identity, the node id would link it back to us.
+    pub async fn request_direct_conversation(&self, pk: &PublicKey) -> Result<()> {
+        let doc = self.clone_doc().await?;
+        if self.identity_service.is_ephemeral(doc.me().await.into()).await? {
+            return Err(GossipError::permission_denied("direct conversations need a real identity, not an anonymous one").into());
+        }
+        let node_id: WideId = doc.1.node_id().into();
+        doc.write_keyed_blob(direct_request_key(*pk).as_str(), node_id).await?;
+        Ok(())
+    }
+
+    async fn direct_request_found(&self, entry: &Entry, doc: &Doc) -> Result<()> {
+        let me = self.persona().await?;
+        let key = key_of(entry);
+        if value_after(DIRECT_REQUEST_PREFIX, &key) != me.to_string() {
+            // not for me
+            return Ok(());
+        }
+        let from: PublicKey = entry.author().into();
+        let peer_node: WideId = doc.read_blob_by_hash(entry.content_hash()).await?;
+        let name = self.get_profile_by_key(&from).await.map(|p| p.name).unwrap_or_default();
+        broadcast(&self.bc, DirectConversationRequested(from, name, peer_node, me))?;
+        Ok(())
+    }
+
     pub async fn post_message(&self, text: String, payload_dir: Option<String>) -> Result<()> {
-        let me = self.identity_service.get_default_identity_pk().await?;
+        let me = self.persona().await?;
         let doc = self.clone_doc().await?;
         // create collection if needed
         let payload = if let Some(dir) = payload_dir {
@@ -746,8 +996,55 @@ impl Service {
         Ok(())
     }
 
+    /// Everything about the current group again, for subscribers that fell behind
+    pub async fn broadcast_snapshot(&self) -> Result<()> {
+        self.broadcast_doc_data().await;
+        broadcast(&self.bc, ConStateUpdated(self.get_con_state().await))?;
+        broadcast(&self.bc, BroadcastingUpdated(self.should_broadcast().await))?;
+        // profiles and messages only exist once a group doc is loaded
+        if self.persona.read().await.is_some() {
+            self.identities_did_update(false).await?;
+            self.broadcast_all_messages().await?;
+        }
+        Ok(())
+    }
+
+    /// Timeline entries are only written to disk while KEEP_CONNECTION_TIMELINE is on
+    async fn record(&self, event: TimelineEvent) {
+        let persist = self.settings_service.get(&KEEP_CONNECTION_TIMELINE).await.unwrap_or(false);
+        self.timeline.add(event, persist).await;
+    }
+
+    /// None until a group doc is loaded
+    pub async fn diagnostics(&self) -> Option<NearbyDiagnostics> {
+        let lock = self.state.read().await;
+        if let Ready { ref doc, ref con_state, found_group, should_scan, should_broadcast, ref statuses, ref ble_peers, .. } = *lock {
+            let ble_peers = ble_peers.iter().map(|(uuid, peer)| BlePeerDiagnostics {
+                uuid: format!("{:?}", uuid),
+                settled: peer.peer_state == PeerState::Settled,
+                doc: postcard::from_bytes::<Capability>(&peer.document_data)
+                    .map(|c| c.id().to_string())
+                    .unwrap_or_else(|e| format!("unreadable: {e}")),
+                nodes: postcard::from_bytes::<Vec<NodeAddr>>(&peer.address_data)
+                    .map(|addrs| addrs.iter().map(|a| a.node_id.to_string()).collect())
+                    .unwrap_or_default(),
+            }).collect();
+            Some(NearbyDiagnostics {
+                doc: doc.id().to_string(),
+                con_state: *con_state,
+                found_group,
+                should_scan,
+                should_broadcast,
+                statuses: statuses.len(),
+                ble_peers
+            })
+        } else {
+            None
+        }
+    }
+
     pub async fn broadcast_all_messages(&self) -> Result<()> {
-        let me = self.identity_service.get_default_identity_pk().await?;
+        let me = self.persona().await?;
         let lock = self.state.read().await;
         if let Ready { ref messages, .. } = *lock {
             let msgs: Vec<DisplayMessage> = messages.posts().await.into_iter().enumerate()
@@ -765,7 +1062,7 @@ impl Service {
             drop(lock);
             Ok(doc)
         } else {
-            Err(anyhow!("bad state, no doc!"))
+            Err(GossipError::not_ready("no group doc loaded yet").into())
         }
     }
 
@@ -789,7 +1086,7 @@ impl Service {
         let found_group = if let Ready { ref mut found_group, ref identities, .. } = *lock {
             if identities.identities_ref().len() > 1 {
                 // we DID find a group
-                println!("we DID find a group, this means we have more than 1 identity");
+                info!("found a group, someone else is on the doc");
 
                 *found_group = true;
                 drop(lock);
@@ -813,36 +1110,92 @@ impl Service {
 
     async fn start_connected_state_loop(&self) {
         let weak = self.get_weak();
+        let mut app_state = self.lifecycle.subscribe();
         tokio::spawn(async move {
-            println!("🌝STARTING A CONSTATE LOOP");
+            debug!("🌝 starting connection state loop");
+            let mut recheck = true;
             loop {
-                if let Some(s) = Self::from_weak(&weak) {
-                    let (old_state, new_state) = s.calculate_and_update_con_state().await.expect("calculate and update con state in loop");
-                    let recheck_delay_seconds = match (old_state, new_state) {
+                let Some(s) = Self::from_weak(&weak) else { break };
+                if recheck {
+                    match s.calculate_and_update_con_state().await {
                         // only really concerned with the connected states
-                        (_, Connected(_)) => {
-                            5
-                        }
-                        (Connected(_), Reconnecting) => {
-                            println!("just detected a disconnection! attempting to reconnected");
-                            s.update_ble_broadcast(true).await.expect("tried to update some bullshit");
-                            s.start_sync().await.unwrap();
-                            5
+                        Ok((Connected(_), Reconnecting)) => {
+                            warn!("lost connection to the group, reconnecting");
+                            if let Err(e) = s.reconnect().await {
+                                report_error("reconnecting to the group", e);
+                            }
                         }
-                        (Reconnecting, Reconnecting) => {
-                            5
-                        }
-                        _ => { 5 }
-                    };
-                    tokio::time::sleep(Duration::from_secs(recheck_delay_seconds)).await;
-                } else {
-                    break;
+                        Ok(_) => {}
+                        Err(e) => report_error("updating connection state", e)
+                    }
+                }
+                // our connections are likely gone anyway, coming back to the foreground
+                // cuts the wait short so a dropped connection is noticed straight away
+                let recheck_delay_seconds = if s.lifecycle.is_background() { 60 } else { 5 };
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(recheck_delay_seconds)) => {
+                        recheck = true;
+                    }
+                    _ = app_state.changed() => {
+                        // going into the background only needs the longer delay picked up
+                        recheck = *app_state.borrow_and_update() == AppState::Foreground;
+                    }
+                    _ = s.con_state_stop.notified() => {
+                        break;
+                    }
                 }
             }
-            println!("😕CONSTATE LOOP ENDED");
+            debug!("😕 connection state loop ended");
         });
     }
 
+    async fn reconnect(&self) -> Result<()> {
+        self.update_ble_broadcast(true).await?;
+        self.start_sync().await
+    }
+
+    /// Stops advertising the group over BLE while suspended. should_broadcast is
+    /// left alone, it's what gets restored on the way back.
+    pub fn enter_background(&self) {
+        self.ble_broadcaster.stop();
+    }
+
+    /// The OS will probably have killed our sockets while we were away without
+    /// us noticing, so sync again. The connection state loop wakes up on its own
+    /// to work out where we actually stand.
+    pub async fn enter_foreground(&self) -> Result<()> {
+        let (should_broadcast, found_group) = match *self.state.read().await {
+            Ready { should_broadcast, found_group, .. } => (should_broadcast, found_group),
+            Uninitialized { .. } => return Ok(())
+        };
+        if should_broadcast {
+            self.ble_broadcaster.start();
+        }
+        self.start_sync().await?;
+        if !found_group {
+            self.evaluate_peers_for_connection().await?;
+        }
+        Ok(())
+    }
+
+    /// Stops the connection state loop, the doc listener and BLE, and closes the
+    /// group doc. Only for when the app host is going away, nothing starts them again.
+    pub async fn shutdown(&self) -> Result<()> {
+        self.con_state_stop.notify_one();
+        self.ble_scanner.stop_scanning();
+        self.ble_broadcaster.stop();
+
+        let mut lock = self.state.write().await;
+        if let Ready { ref doc, ref doc_stop, ref mut should_scan, ref mut should_broadcast, .. } = *lock {
+            *should_scan = false;
+            *should_broadcast = false;
+            // notify_one, the listener may be busy with an entry rather than waiting
+            doc_stop.notify_one();
+            doc.close().await?;
+        }
+        Ok(())
+    }
+
     pub async fn calculate_and_update_con_state(&self) -> Result<(ConState, ConState)> {
         let mut lock = self.state.write().await;
         if let Ready { ref found_group, ref should_broadcast, ref should_scan, ref doc, ref mut con_state, .. } = *lock {
@@ -857,7 +1210,7 @@ impl Service {
                 // or if its been too long
                 // (true, _, false, 0) => { Disconnected }
                 _ => {
-                    println!("some weird state");
+                    warn!(found_group, should_broadcast, should_scan, active_peers, "connection state doesn't add up");
                     Invalid
                 }
             };
@@ -867,6 +1220,7 @@ impl Service {
                 *con_state = new_state;
                 drop(lock);
                 broadcast(&self.bc, ConStateUpdated(new_state))?;
+                self.record(TimelineEvent::ConStateChanged { from: old_state, to: new_state }).await;
             }
             Ok((old_state, new_state))
         } else {
@@ -874,6 +1228,26 @@ impl Service {
         }
     }
 
+    async fn announce_contacts_nearby(&self, profiles: &[NearbyProfile]) -> Result<()> {
+        let me = self.persona().await?;
+        let newly_seen: Vec<&NearbyProfile> = {
+            let mut lock = self.state.write().await;
+            if let Ready { ref mut announced_contacts, .. } = *lock {
+                profiles.iter()
+                    .filter(|p| p.is_contact && p.pk != me)
+                    .filter(|p| announced_contacts.insert(p.pk))
+                    .collect()
+            } else { vec![] }
+        };
+        for p in newly_seen {
+            if let Some(contact) = self.contacts_service.contact_seen(p).await? {
+                info!(contact = %contact.pk, name = %contact.name, "friend nearby");
+                broadcast(&self.bc, ContactNearby(contact))?;
+            }
+        }
+        Ok(())
+    }
+
     pub async fn status_update(&self, pk: PublicKey, status: Status) -> Result<()> {
         let mut lock = self.state.write().await;
         if let Ready { ref mut statuses, .. } = *lock {
@@ -886,6 +1260,15 @@ impl Service {
     }
 }
 
+fn sync_timeline_event(doc: WideId, event: LiveEvent) -> Option<TimelineEvent> {
+    match event {
+        LiveEvent::NeighborUp(peer) => Some(TimelineEvent::NeighborUp { doc, peer: peer.into() }),
+        LiveEvent::NeighborDown(peer) => Some(TimelineEvent::NeighborDown { doc, peer: peer.into() }),
+        LiveEvent::SyncFinished(sync) => Some(TimelineEvent::SyncFinished { doc, peer: sync.peer.into(), error: sync.result.err() }),
+        _ => None
+    }
+}
+
 fn get_document_data(ticket: &DocTicket) -> DocumentData {
     postcard::to_stdvec(&ticket.capability).expect("serializing document data")
 }
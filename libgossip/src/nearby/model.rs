@@ -3,11 +3,11 @@ use serde::{Deserialize, Serialize};
 use crate::blob_dispatcher::NamedBlob;
 
 use crate::data::{BlobHash, PublicKey, WideId};
-use crate::nearby::MESSAGE_PAYLOADS;
 use crate::nearby::MESSAGES;
+use crate::record::Record;
 
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
 #[derive(uniffi::Enum)]
 pub enum ConState {
     Offline,
@@ -19,18 +19,43 @@ pub enum ConState {
     Invalid
 }
 
+/// What the nearby service is up to, for the diagnostics bundle
+#[derive(Serialize, Debug)]
+pub struct NearbyDiagnostics {
+    pub doc: String,
+    pub con_state: ConState,
+    pub found_group: bool,
+    pub should_scan: bool,
+    pub should_broadcast: bool,
+    pub statuses: usize,
+    pub ble_peers: Vec<BlePeerDiagnostics>,
+}
+
+/// A peer as we last heard of it over bluetooth
+#[derive(Serialize, Debug)]
+pub struct BlePeerDiagnostics {
+    pub uuid: String,
+    pub settled: bool,
+    // the doc it's advertising and the nodes to reach it on
+    pub doc: String,
+    pub nodes: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 #[derive(uniffi::Record)]
 pub struct Status {
     pub(crate) text: String
 }
 
+impl Record for Status {}
+
 #[derive(uniffi::Record, Clone, Debug)]
 pub struct NearbyProfile {
     pub pk: PublicKey,
     pub name: String,
     pub pic: Option<BlobHash>,
-    pub status: Status
+    pub status: Status,
+    pub is_contact: bool
 }
 
 #[derive(Debug, Clone)]
@@ -53,10 +78,6 @@ pub fn message_key(msg: &Post) -> String {
     format!("{MESSAGES}/{}",msg.created_at.to_string())
 }
 
-pub fn message_payload_key(msg: &Post) -> String {
-    format!("{MESSAGE_PAYLOADS}/{}",msg.created_at.to_string())
-}
-
 #[derive(uniffi::Record)]
 
 pub struct BioDetails {
@@ -75,6 +96,8 @@ pub struct Post {
     pub payload: Option<BlobHash>
 }
 
+impl Record for Post {}
+
 
 impl Post {
     pub fn new(pk: PublicKey) -> Self {
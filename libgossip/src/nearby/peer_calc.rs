@@ -2,7 +2,9 @@ use std::collections::HashMap;
 use crate::ble::{AddressData, DocumentData, PeerData};
 use crate::ble::PeerState::Settled;
 use crate::data::UUID;
+use tracing::{debug, instrument};
 
+#[instrument(skip_all, fields(peers = peers.len()))]
 pub fn find_best_doc_from_peers(my_exchange_doc: &DocumentData, peers: &HashMap<UUID, PeerData>) -> DocumentData {
     let (non_scan_peers , scan_peers): (Vec<&PeerData>, Vec<&PeerData>) = peers.values().partition(|&n| n.peer_state == Settled );
 
@@ -19,10 +21,10 @@ pub fn find_best_doc_from_peers(my_exchange_doc: &DocumentData, peers: &HashMap<
     // check the non-scanning folks first
     if let Some((doc, cnt)) = most_freq {
         return if cnt > 1 {
-            println!("gonna return a nonscanner with multiple ppl!");
+            debug!(count = cnt, "picking the doc most settled peers are on");
             doc.clone()
         } else {
-            println!("gonna return a nonscanner that is smalleset");
+            debug!("picking the smallest doc a settled peer is on");
             // there is no most frequent - pick the smallest valued
             let smallest = smallest(non_scan_docs.iter());
             smallest.unwrap().clone()
@@ -31,7 +33,7 @@ pub fn find_best_doc_from_peers(my_exchange_doc: &DocumentData, peers: &HashMap<
 
     //check the scanning
     scan_docs.push(my_exchange_doc);
-    println!("gonna return regular smallest scanner");
+    debug!(scanning = scan_docs.len(), "picking the smallest doc of everyone still scanning");
     let desired_doc = smallest(scan_docs.iter());
     desired_doc.unwrap().clone()
 }
@@ -4,14 +4,16 @@ use std::sync::Weak;
 
 use anyhow::Result;
 use async_trait::async_trait;
-use iroh::client::blobs::BlobStatus;
+use iroh::client::docs::Entry;
 use iroh::docs::store::{Query, SortBy, SortDirection};
 use tokio::spawn;
 
 use crate::doc::{Doc, InsertEntry};
-use crate::events::WeakService;
+use crate::events::{report_error, WeakService};
+use crate::group_key::GroupKey;
 use crate::nearby::MESSAGES;
-use crate::nearby::model::{message_key, message_payload_key, Post};
+use crate::nearby::model::{message_key, Post};
+use crate::quarantine::Quarantine;
 
 #[async_trait]
 pub trait PostDomainResponder: Send + Sync + 'static {
@@ -21,7 +23,9 @@ pub trait PostDomainResponder: Send + Sync + 'static {
 pub struct PostDomain<S, I>
 {
     doc: Doc,
+    group_key: GroupKey,
     posts: Vec<Post>,
+    quarantine: Quarantine,
     responder: Weak<I>,
     _phantom: PhantomData<S>
 }
@@ -30,22 +34,29 @@ impl<S, I> PostDomain<S, I>
 where
     S: PostDomainResponder + WeakService<I,S>
 {
-    pub fn new(doc: &Doc, responder: &S) -> Self
+    pub fn new(doc: &Doc, group_key: GroupKey, quarantine: Quarantine, responder: &S) -> Self
     {
         PostDomain {
             doc: doc.clone(),
+            group_key,
             posts: vec![],
+            quarantine,
             responder: responder.get_weak(),
             _phantom: PhantomData
         }
     }
-    pub fn set_doc(&mut self, doc: &Doc) {
-        self.doc = doc.clone()
+    pub fn set_doc(&mut self, doc: &Doc, group_key: GroupKey) {
+        self.doc = doc.clone();
+        self.group_key = group_key;
     }
     pub async fn initialize(&mut self) -> Result<()> {
         self.posts.clear();
-        let posts: Vec<Post> = self.doc.read_blobs_by_query(Query::key_prefix(MESSAGES).sort_by(SortBy::KeyAuthor, SortDirection::Asc)).await?;
-        self.posts = posts;
+        let entries = self.doc.list_entries_by_query(Query::key_prefix(MESSAGES).sort_by(SortBy::KeyAuthor, SortDirection::Asc)).await?;
+        for entry in entries {
+            if let Some(post) = self.read_post(&entry).await {
+                self.posts.push(post);
+            }
+        }
         Ok(())
     }
 
@@ -53,17 +64,11 @@ where
         self.posts.clone()
     }
 
+    /// The payload hash only travels inside the sealed post. It isn't given an entry
+    /// of its own, so only members can learn it and fetch the collection from a peer.
     pub async fn create_post(&self, p: Post) -> Result<()> {
         let key = message_key(&p);
-        self.doc.write_keyed_blob(&key, &p).await?;
-        if let Some(b) = p.payload {
-            if let BlobStatus::Complete { size } = self.doc.blobs().status(b.into()).await? {
-                let key = message_payload_key(&p);
-                self.doc.set_hash(p.pk.into(), key, b.into(), size).await?;
-            } else {
-                eprintln!("failed to add payload to message")
-            };
-        }
+        self.doc.write_sealed_keyed_blob(&self.group_key, &key, p).await?;
         Ok(())
     }
 
@@ -72,11 +77,22 @@ where
     }
 
     pub async fn insert_entry(&mut self, e: InsertEntry) -> Result<()> {
-        let post: Post = self.doc.read_blob_by_hash(e.entry.content_hash().into()).await?;
-        self.insert_post(post).await?;
+        if let Some(post) = self.read_post(&e.entry).await {
+            self.insert_post(post).await?;
+        }
         Ok(())
     }
 
+    async fn read_post(&self, entry: &Entry) -> Option<Post> {
+        match self.doc.read_sealed_blob_by_hash(&self.group_key, entry.content_hash()).await {
+            Ok(post) => Some(post),
+            Err(e) => {
+                self.quarantine.add(self.doc.id().into(), entry, &e).await;
+                None
+            }
+        }
+    }
+
 
 
     async fn insert_post(&mut self, p: Post) -> Result<()> {
@@ -97,7 +113,11 @@ where
             });
             if let Some(resp) = S::from_weak(&self.responder) {
                 let posts = self.posts.clone();
-                spawn(async move { resp.all_posts_updated(posts).await.expect("fuck") });
+                spawn(async move {
+                    if let Err(e) = resp.all_posts_updated(posts).await {
+                        report_error("failed updating posts", e);
+                    }
+                });
 
             }
         } else {
@@ -122,6 +142,10 @@ where
     S: WeakService<I, S> + Send +'static
 {
     if let Some(resp) = S::from_weak(weak) {
-        spawn(async move { call(resp).await.unwrap() });
+        spawn(async move {
+            if let Err(e) = call(resp).await {
+                report_error("failed updating posts", e);
+            }
+        });
     }
 }
\ No newline at end of file
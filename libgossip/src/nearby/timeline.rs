@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io::Write;
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::RwLock;
+use tracing::warn;
+
+use crate::data::{PublicKey, UUID, WideId};
+use crate::events::{broadcast, create_broadcast, subscribe_with_snapshot};
+use crate::nearby::model::ConState;
+
+// a few hours of a flaky connection
+const MAX_TIMELINE: usize = 500;
+pub const TIMELINE_FILE: &str = "connection_timeline.jsonl";
+
+/// Something that happened to our connection with the group. The polled ConState
+/// only ever shows where we ended up, these show how we got there.
+#[derive(uniffi::Enum, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TimelineEvent {
+    ConStateChanged { from: ConState, to: ConState },
+    // only when a peer is new or changed what it's advertising
+    BlePeerSeen { peer: UUID, settled: bool },
+    DocLoaded { doc: WideId },
+    SyncStarted { doc: WideId },
+    NeighborUp { doc: WideId, peer: PublicKey },
+    NeighborDown { doc: WideId, peer: PublicKey },
+    SyncFinished { doc: WideId, peer: PublicKey, error: Option<String> },
+}
+
+#[derive(uniffi::Record, Serialize, Deserialize, Clone, Debug)]
+pub struct TimelineEntry {
+    pub at: SystemTime,
+    pub event: TimelineEvent
+}
+
+/// The last MAX_TIMELINE events, oldest first. When persisting is on every entry
+/// is also appended to a file, which is trimmed back down on the next start.
+#[derive(Clone)]
+pub struct Timeline(Arc<InnerTimeline>);
+
+pub struct InnerTimeline {
+    entries: RwLock<VecDeque<TimelineEntry>>,
+    bc: Sender<TimelineEntry>,
+    path: PathBuf
+}
+
+impl Deref for Timeline {
+    type Target = InnerTimeline;
+    fn deref(&self) -> &Self::Target { self.0.as_ref() }
+}
+
+impl Timeline {
+    /// Starts with whatever an earlier run persisted to path
+    pub fn new(path: PathBuf) -> Self {
+        let entries = load(&path);
+        if !entries.is_empty() {
+            if let Err(e) = save(&path, &entries) {
+                warn!("couldn't trim the connection timeline: {e}");
+            }
+        }
+        Timeline(Arc::new(InnerTimeline {
+            entries: RwLock::new(entries),
+            bc: create_broadcast(),
+            path
+        }))
+    }
+
+    pub async fn add(&self, event: TimelineEvent, persist: bool) {
+        let entry = TimelineEntry { at: SystemTime::now(), event };
+        {
+            let mut entries = self.entries.write().await;
+            if entries.len() == MAX_TIMELINE {
+                entries.pop_front();
+            }
+            entries.push_back(entry.clone());
+        }
+        if persist {
+            if let Err(e) = append(&self.path, &entry) {
+                warn!("couldn't persist connection timeline entry: {e}");
+            }
+        }
+        let _ = broadcast(&self.bc, entry);
+    }
+
+    pub async fn list(&self) -> Vec<TimelineEntry> {
+        self.entries.read().await.iter().cloned().collect()
+    }
+
+    pub async fn subscribe_with_snapshot(&self) -> anyhow::Result<(Vec<TimelineEntry>, Receiver<TimelineEntry>)> {
+        subscribe_with_snapshot(&self.bc, || async { Ok(self.list().await) }).await
+    }
+}
+
+fn load(path: &PathBuf) -> VecDeque<TimelineEntry> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return VecDeque::new();
+    };
+    let mut entries: VecDeque<TimelineEntry> = contents.lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    while entries.len() > MAX_TIMELINE {
+        entries.pop_front();
+    }
+    entries
+}
+
+fn save(path: &PathBuf, entries: &VecDeque<TimelineEntry>) -> anyhow::Result<()> {
+    let mut file = fs::File::create(path)?;
+    for entry in entries {
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    }
+    Ok(())
+}
+
+fn append(path: &PathBuf, entry: &TimelineEntry) -> anyhow::Result<()> {
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+#[test]
+pub fn timeline_is_bounded_and_reloaded_from_disk() {
+    let dir = std::env::temp_dir().join(format!("gossip-timeline-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(TIMELINE_FILE);
+    let _ = fs::remove_file(&path);
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let timeline = Timeline::new(path.clone());
+        for i in 0..MAX_TIMELINE + 10 {
+            let to = if i % 2 == 0 { ConState::Searching } else { ConState::Reconnecting };
+            timeline.add(TimelineEvent::ConStateChanged { from: ConState::Offline, to }, true).await;
+        }
+        // not persisted
+        timeline.add(TimelineEvent::ConStateChanged { from: ConState::Searching, to: ConState::Connected(1) }, false).await;
+        let entries = timeline.list().await;
+        assert_eq!(entries.len(), MAX_TIMELINE);
+        assert_eq!(entries.last().unwrap().event, TimelineEvent::ConStateChanged { from: ConState::Searching, to: ConState::Connected(1) });
+
+        let reloaded = Timeline::new(path.clone()).list().await;
+        assert_eq!(reloaded.len(), MAX_TIMELINE);
+        assert_eq!(reloaded.last().unwrap().event, TimelineEvent::ConStateChanged { from: ConState::Offline, to: ConState::Reconnecting });
+    });
+    fs::remove_dir_all(dir).unwrap();
+}
+
@@ -0,0 +1,54 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use iroh::base::node_addr::AddrInfoOptions::RelayAndAddresses;
+use iroh::client::docs::ShareMode::Write;
+use iroh::docs::{Author, DocTicket};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::doc::{Doc, Node, write_fs_reference};
+use crate::identity::IdentityService;
+use crate::views::errors::GossipError;
+
+const PAIRING_PREFIX: &str = "gossippair";
+
+/// Everything another of our devices needs to share the root settings doc: write
+/// access to it and the author that owns it. Anyone holding this can become us,
+/// so it should only ever be shown as a QR code on our own screen.
+#[derive(Serialize, Deserialize)]
+struct PairingTicket {
+    doc: String,
+    author: [u8; 32],
+}
+
+pub async fn create_pairing_ticket(root_doc: &Doc, identity: &IdentityService) -> Result<String> {
+    // identities we already have should come along too
+    identity.share_identity_secrets().await?;
+
+    let author = root_doc.authors().export(root_doc.me().await).await?
+        .ok_or_else(|| anyhow!("no secret for the root doc author"))?;
+    // sharing also starts syncing, so we're ready when the other device shows up
+    let doc = root_doc.share(Write, RelayAndAddresses).await?;
+    let ticket = PairingTicket { doc: doc.to_string(), author: author.to_bytes() };
+    Ok(format!("{PAIRING_PREFIX}{}", URL_SAFE_NO_PAD.encode(postcard::to_stdvec(&ticket)?)))
+}
+
+/// Switches this node over to the root doc of the device that made the ticket. The
+/// settings doc is only picked up when the app starts, so this takes effect on the
+/// next launch. Whatever identity this device had before is left behind.
+pub async fn pair_with_device(node: &Node, reference_path: impl AsRef<Path>, ticket: &str) -> Result<()> {
+    let encoded = ticket.trim().strip_prefix(PAIRING_PREFIX)
+        .ok_or_else(|| GossipError::invalid_input("not a pairing ticket"))?;
+    let ticket: PairingTicket = postcard::from_bytes(&URL_SAFE_NO_PAD.decode(encoded)?)?;
+
+    let author = Author::from_bytes(&ticket.author);
+    node.authors().import(author.clone()).await?;
+    let doc = node.docs().import(DocTicket::from_str(&ticket.doc)?).await?;
+    write_fs_reference(reference_path, doc.id(), Some(author.id())).await?;
+    info!(doc = %doc.id(), "paired with root doc, restart to use it");
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use iroh::client::docs::Entry;
+use tokio::sync::RwLock;
+use tracing::warn;
+
+use crate::data::{BlobHash, PublicKey, WideId};
+use crate::doc::key_of;
+
+// plenty to see what's going on, not enough for a peer spamming junk to matter
+const MAX_QUARANTINED: usize = 200;
+
+/// An entry from a doc we couldn't decode. It's kept out of the domain it was
+/// meant for, so one bad entry from a buggy or malicious peer can't take the app
+/// down, and held on to here so it can be looked at later.
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct QuarantinedEntry {
+    pub doc_id: WideId,
+    pub key: String,
+    pub author: PublicKey,
+    pub hash: BlobHash,
+    pub reason: String,
+    pub at: SystemTime
+}
+
+/// Shared by every domain reading entries from peers, newest entries last
+#[derive(Clone)]
+pub struct Quarantine(Arc<RwLock<VecDeque<QuarantinedEntry>>>);
+
+impl Quarantine {
+    pub fn new() -> Self {
+        Quarantine(Arc::new(RwLock::new(VecDeque::new())))
+    }
+
+    pub async fn add(&self, doc_id: WideId, entry: &Entry, reason: &anyhow::Error) {
+        let quarantined = QuarantinedEntry {
+            doc_id,
+            key: key_of(entry).into_owned(),
+            author: entry.author().into(),
+            hash: entry.content_hash().into(),
+            reason: reason.to_string(),
+            at: SystemTime::now()
+        };
+        warn!(doc = %doc_id, key = %quarantined.key, author = %quarantined.author, "quarantined entry: {reason}");
+
+        let mut entries = self.0.write().await;
+        // the same entry is read again every time its doc is loaded
+        if entries.iter().any(|e| e.doc_id == doc_id && e.hash == quarantined.hash) {
+            return;
+        }
+        if entries.len() == MAX_QUARANTINED {
+            entries.pop_front();
+        }
+        entries.push_back(quarantined);
+    }
+
+    pub async fn list(&self) -> Vec<QuarantinedEntry> {
+        self.0.read().await.iter().cloned().collect()
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use flexbuffers::Reader;
+use serde::{Deserialize, Serialize};
+
+use crate::data::WideId;
+
+const VERSION_FIELD: &str = "__v";
+const DATA_FIELD: &str = "d";
+
+/// Anything we write into a doc as a flexbuffers blob. Every record is wrapped with
+/// the version of its shape so readers can upgrade old records and leave alone the
+/// ones written by a newer build.
+///
+/// Adding a field with `#[serde(default)]` doesn't need a new version, renaming,
+/// removing or retyping one does: bump VERSION and teach upgrade the old shape.
+pub trait Record: Serialize + for<'a> Deserialize<'a> {
+    const VERSION: u32 = 1;
+
+    /// Turn a record written at an older version into the current shape. Records
+    /// from before there were versions at all come through as version 0.
+    fn upgrade(_version: u32, r: Reader<&[u8]>) -> Result<Self> {
+        Ok(Self::deserialize(r)?)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RecordError {
+    #[error("record is version {found}, this build only knows up to {known}")]
+    NewerVersion { found: u32, known: u32 }
+}
+
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    #[serde(rename = "__v")]
+    version: u32,
+    d: &'a T
+}
+
+pub fn encode<T: Record>(data: &T) -> Result<Vec<u8>> {
+    let mut s = flexbuffers::FlexbufferSerializer::new();
+    Envelope { version: T::VERSION, d: data }.serialize(&mut s)?;
+    Ok(s.take_buffer())
+}
+
+pub fn decode<T: Record>(bytes: &[u8]) -> Result<T> {
+    let root = Reader::get_root(bytes)?;
+    let (version, data) = unwrap_envelope(root)?;
+    if version > T::VERSION {
+        return Err(RecordError::NewerVersion { found: version, known: T::VERSION }.into());
+    }
+    if version == T::VERSION {
+        Ok(T::deserialize(data)?)
+    } else {
+        T::upgrade(version, data)
+    }
+}
+
+/// True for the error decode gives back for records from a newer build, which
+/// callers skip rather than fail on
+pub fn is_newer_version(e: &anyhow::Error) -> bool {
+    matches!(e.downcast_ref::<RecordError>(), Some(RecordError::NewerVersion { .. }))
+}
+
+fn unwrap_envelope(root: Reader<&[u8]>) -> Result<(u32, Reader<&[u8]>)> {
+    if root.flexbuffer_type().is_map() {
+        let map = root.as_map();
+        if let (Ok(version), Ok(data)) = (map.index(VERSION_FIELD), map.index(DATA_FIELD)) {
+            if map.len() == 2 {
+                return Ok((version.get_u64()? as u32, data));
+            }
+        }
+    }
+    // written before records had versions
+    Ok((0, root))
+}
+
+impl Record for bool {}
+impl Record for String {}
+impl Record for WideId {}
+impl Record for [u8; 32] {}
+impl<T: Record> Record for Vec<T> {}
+impl<T: Record> Record for HashMap<String, T> {}
+
+#[test]
+pub fn old_records_are_upgraded_and_newer_ones_refused() {
+    use crate::nearby::model::Status;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Renamed {
+        label: String
+    }
+    impl Record for Renamed {
+        const VERSION: u32 = 2;
+
+        fn upgrade(_version: u32, r: Reader<&[u8]>) -> Result<Self> {
+            // before version 2 it was a Status
+            Ok(Renamed { label: Status::deserialize(r)?.text })
+        }
+    }
+
+    let status = Status { text: String::from("hi") };
+    let mut s = flexbuffers::FlexbufferSerializer::new();
+    status.serialize(&mut s).unwrap();
+    let unversioned: Status = decode(s.view()).unwrap();
+    assert_eq!(unversioned, status);
+
+    let upgraded: Renamed = decode(&encode(&status).unwrap()).unwrap();
+    assert_eq!(upgraded, Renamed { label: String::from("hi") });
+
+    let newer = encode(&upgraded).unwrap();
+    assert!(is_newer_version(&decode::<Status>(&newer).unwrap_err()));
+}
@@ -3,35 +3,57 @@ use std::fmt::Debug;
 use std::ops::Deref;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use anyhow::Result;
-use futures_lite::StreamExt;
+use futures_lite::{Stream, StreamExt};
 use iroh::base::key::PublicKey;
 use iroh::blobs::format::collection::Collection;
 use iroh::blobs::store::Store;
 use iroh::blobs::util::SetTagOption;
-use iroh::client::blobs::{AddOutcome, BlobStatus};
 use iroh::client::docs::Entry;
 use iroh::docs::NamespaceId;
+use iroh::docs::store::Query;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::{Notify, RwLock};
+use tracing::{info, warn};
 
 pub use Service as SettingsService;
+pub use self::registry::{ANONYMOUS_IN_NEW_GROUPS, CURRENT_NEARBY_DOC_ID, KEEP_CONNECTION_TIMELINE, Setting, SHARE_NEARBY_PUBLIC_BIO};
+use self::registry::{all_settings, find_setting, SettingKind};
+
+pub mod registry;
+pub mod json;
 use crate::blob_dispatcher::NamedBlob;
+use crate::record::Record;
 use crate::data::{BlobHash, WideId};
 
-use crate::doc::{Doc, key_of};
-use crate::events::{broadcast, create_broadcast};
+use crate::doc::{Doc, InsertEntry, key_of, value_after};
+use crate::events::{broadcast, create_broadcast, report_error};
 use crate::nearby::BIO;
-use crate::nearby::model::{BioDetails, message_payload_key, Status};
+use crate::nearby::model::Status;
 use crate::settings::SettingsEvent::{OwnPublicBioUpdated, StatusSettingChanged};
+use crate::views::errors::GossipError;
 
 const NODE_SETTINGS_FILE: &str = "node_root_settings_doc.bin";
 
 pub const CURRENT_STATUS_SETTING: &str = "current_status";
-pub const CURRENT_NEARBY_DOC_ID: &str = "current_nearby_doc_id";
 
+// settings used to be one blob under this key, see migrate_legacy_store
 pub const SETTINGS_STORE_KEY: &str = "settings_store";
+pub const SETTINGS_PREFIX: &str = "settings";
+
+pub fn setting_key(store_key: &str) -> String {
+    format!("{SETTINGS_PREFIX}/{store_key}")
+}
+
+// device local settings are stored with the node id after their key
+fn is_device_local(key: &str) -> bool {
+    all_settings().iter().any(|s| s.is_device_local() && key.starts_with(s.key()))
+}
 
 #[derive(Clone, Debug)]
 pub enum SettingsEvent {
@@ -45,6 +67,9 @@ pub struct Service(Arc<ServiceInner>);
 pub struct ServiceInner {
     bc: Sender<SettingsEvent>,
     root_doc: Doc,
+    // last value we knew for each setting, so changes can say what they replaced
+    last_seen: RwLock<HashMap<String, StoreValue>>,
+    stop: Notify,
 }
 
 impl Deref for Service {
@@ -55,25 +80,19 @@ impl Deref for Service {
     }
 }
 
-
-pub const SHARE_NEARBY_PUBLIC_BIO: &str = "share_nearby_public_bio";
-
-// pub enum SettingKey {
-//     ShareNearbyPublicBio("THING")
-// }
-
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(uniffi::Enum)]
 pub enum StoreValue {
-    Bool(bool), String(String), Int(i32), WideId(WideId)
+    Bool(bool), String(String), Int(i32), WideId(WideId),
+    // never rename or remove a variant, stored settings are decoded by its name
+    Float(f64), Long(i64), Duration(Duration), Timestamp(SystemTime),
+    List(Vec<StoreValue>), Map(HashMap<String, StoreValue>)
 }
 #[derive(Serialize, Deserialize)]
 struct SettingsStore(HashMap<String, StoreValue>);
-impl SettingsStore {
-    fn new() -> Self {
-        SettingsStore(HashMap::new())
-    }
 
-}
+impl Record for StoreValue {}
+impl Record for SettingsStore {}
 
 impl <T> From<StoreValue> for Option<T>
 where
@@ -85,11 +104,16 @@ where
 }
 
 
-trait OptionValue: Sized + Debug {
+pub trait OptionValue: Sized + Debug + Sync {
+    const KIND: SettingKind;
     fn from_store_value(v: StoreValue) -> Option<Self>;
     fn make_store_value(v: Self) -> StoreValue;
+    // how it looks in exported settings json
+    fn to_json(v: &Self) -> Value;
+    fn from_json(v: &Value) -> Option<Self>;
 }
 impl OptionValue for bool {
+    const KIND: SettingKind = SettingKind::Bool;
     fn from_store_value(v: StoreValue) -> Option<Self> {
         match v {
             StoreValue::Bool(b) => Some(b),
@@ -100,9 +124,16 @@ impl OptionValue for bool {
     fn make_store_value(v: Self) -> StoreValue {
         StoreValue::Bool(v)
     }
+    fn to_json(v: &Self) -> Value {
+        Value::Bool(*v)
+    }
+    fn from_json(v: &Value) -> Option<Self> {
+        v.as_bool()
+    }
 }
 
 impl OptionValue for WideId {
+    const KIND: SettingKind = SettingKind::WideId;
     fn from_store_value(v: StoreValue) -> Option<Self> {
         match v {
             StoreValue::WideId(w) => Some(w),
@@ -114,8 +145,15 @@ impl OptionValue for WideId {
     fn make_store_value(v: Self) -> StoreValue {
         StoreValue::WideId(v)
     }
+    fn to_json(v: &Self) -> Value {
+        Value::String(v.to_string())
+    }
+    fn from_json(v: &Value) -> Option<Self> {
+        v.as_str().and_then(|s| s.parse().ok())
+    }
 }
 impl OptionValue for i32 {
+    const KIND: SettingKind = SettingKind::Int;
     fn from_store_value(v: StoreValue) -> Option<Self> {
         match v {
             StoreValue::Int(i) => Some(i),
@@ -126,8 +164,15 @@ impl OptionValue for i32 {
     fn make_store_value(v: Self) -> StoreValue {
         StoreValue::Int(v)
     }
+    fn to_json(v: &Self) -> Value {
+        Value::from(*v)
+    }
+    fn from_json(v: &Value) -> Option<Self> {
+        v.as_i64().and_then(|i| i32::try_from(i).ok())
+    }
 }
 impl OptionValue for String {
+    const KIND: SettingKind = SettingKind::String;
     fn from_store_value(v: StoreValue) -> Option<Self> {
         match v {
             StoreValue::String(s) => Some(s),
@@ -137,6 +182,129 @@ impl OptionValue for String {
     fn make_store_value(v: Self) -> StoreValue {
         StoreValue::String(v)
     }
+    fn to_json(v: &Self) -> Value {
+        Value::String(v.clone())
+    }
+    fn from_json(v: &Value) -> Option<Self> {
+        v.as_str().map(String::from)
+    }
+}
+
+impl OptionValue for f64 {
+    const KIND: SettingKind = SettingKind::Float;
+    fn from_store_value(v: StoreValue) -> Option<Self> {
+        match v {
+            StoreValue::Float(f) => Some(f),
+            // whole numbers are fine where a float is wanted
+            StoreValue::Int(i) => Some(i as f64),
+            StoreValue::Long(i) => Some(i as f64),
+            _ => None
+        }
+    }
+    fn make_store_value(v: Self) -> StoreValue {
+        StoreValue::Float(v)
+    }
+    fn to_json(v: &Self) -> Value {
+        Value::from(*v)
+    }
+    fn from_json(v: &Value) -> Option<Self> {
+        v.as_f64()
+    }
+}
+impl OptionValue for i64 {
+    const KIND: SettingKind = SettingKind::Long;
+    fn from_store_value(v: StoreValue) -> Option<Self> {
+        match v {
+            StoreValue::Long(i) => Some(i),
+            StoreValue::Int(i) => Some(i as i64),
+            _ => None
+        }
+    }
+    fn make_store_value(v: Self) -> StoreValue {
+        StoreValue::Long(v)
+    }
+    fn to_json(v: &Self) -> Value {
+        Value::from(*v)
+    }
+    fn from_json(v: &Value) -> Option<Self> {
+        v.as_i64()
+    }
+}
+impl OptionValue for Duration {
+    const KIND: SettingKind = SettingKind::Duration;
+    fn from_store_value(v: StoreValue) -> Option<Self> {
+        match v {
+            StoreValue::Duration(d) => Some(d),
+            _ => None
+        }
+    }
+    fn make_store_value(v: Self) -> StoreValue {
+        StoreValue::Duration(v)
+    }
+    fn to_json(v: &Self) -> Value {
+        // in seconds
+        Value::from(v.as_secs_f64())
+    }
+    fn from_json(v: &Value) -> Option<Self> {
+        v.as_f64().and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+    }
+}
+impl OptionValue for SystemTime {
+    const KIND: SettingKind = SettingKind::Timestamp;
+    fn from_store_value(v: StoreValue) -> Option<Self> {
+        match v {
+            StoreValue::Timestamp(t) => Some(t),
+            _ => None
+        }
+    }
+    fn make_store_value(v: Self) -> StoreValue {
+        StoreValue::Timestamp(v)
+    }
+    fn to_json(v: &Self) -> Value {
+        Value::String(DateTime::<Utc>::from(*v).to_rfc3339())
+    }
+    fn from_json(v: &Value) -> Option<Self> {
+        v.as_str().and_then(|s| DateTime::parse_from_rfc3339(s).ok()).map(SystemTime::from)
+    }
+}
+impl <T: OptionValue> OptionValue for Vec<T> {
+    const KIND: SettingKind = SettingKind::List;
+    fn from_store_value(v: StoreValue) -> Option<Self> {
+        match v {
+            // every item has to fit, a list with one bad item is a bad list
+            StoreValue::List(items) => items.into_iter().map(T::from_store_value).collect(),
+            _ => None
+        }
+    }
+    fn make_store_value(v: Self) -> StoreValue {
+        StoreValue::List(v.into_iter().map(T::make_store_value).collect())
+    }
+    fn to_json(v: &Self) -> Value {
+        Value::Array(v.iter().map(T::to_json).collect())
+    }
+    fn from_json(v: &Value) -> Option<Self> {
+        v.as_array()?.iter().map(T::from_json).collect()
+    }
+}
+impl <T: OptionValue> OptionValue for HashMap<String, T> {
+    const KIND: SettingKind = SettingKind::Map;
+    fn from_store_value(v: StoreValue) -> Option<Self> {
+        match v {
+            StoreValue::Map(entries) => entries.into_iter()
+                .map(|(k, v)| T::from_store_value(v).map(|v| (k, v)))
+                .collect(),
+            _ => None
+        }
+    }
+    fn make_store_value(v: Self) -> StoreValue {
+        StoreValue::Map(v.into_iter().map(|(k, v)| (k, T::make_store_value(v))).collect())
+    }
+    fn to_json(v: &Self) -> Value {
+        Value::Object(v.iter().map(|(k, v)| (k.clone(), T::to_json(v))).collect())
+    }
+    fn from_json(v: &Value) -> Option<Self> {
+        v.as_object()?.iter().map(|(k, v)| T::from_json(v).map(|v| (k.clone(), v))).collect()
+    }
 }
 
 impl <T: OptionValue> From<T> for StoreValue {
@@ -146,34 +314,70 @@ impl <T: OptionValue> From<T> for StoreValue {
 }
 
 impl Service {
-    pub fn new(root_doc: Doc) -> Service {
+    /// Subscribes to the root doc before returning, so a setting changed straight after
+    /// still gets announced
+    pub async fn new(root_doc: Doc) -> Service {
         let inner = Arc::new(ServiceInner {
             bc: create_broadcast(),
-            root_doc
+            root_doc,
+            last_seen: RwLock::new(HashMap::new()),
+            stop: Notify::new(),
         });
         let s = Service(inner);
-        let o = s.clone();
-        tokio::spawn(async move { o.doc_watch_loop().await });
+        match s.watch_root_doc().await {
+            Ok(stream) => {
+                let o = s.clone();
+                tokio::spawn(async move { o.doc_watch_loop(stream).await });
+            }
+            Err(e) => report_error("watching the settings doc", e)
+        }
         s
     }
 
-    pub async fn doc_watch_loop(&self) -> Result<()> {
-        let mut stream = self.root_doc.subscribe().await?;
-        while let Some(e) = stream.next().await {
+    async fn watch_root_doc(&self) -> Result<impl Stream<Item = InsertEntry>> {
+        self.migrate_legacy_store().await?;
+        // nobody has read a setting yet, so what's stored now is what changes are reported against
+        self.seed_last_seen().await?;
+        self.root_doc.subscribe().await
+    }
+
+    async fn doc_watch_loop(&self, mut stream: impl Stream<Item = InsertEntry> + Unpin) -> Result<()> {
+        loop {
+            let e = tokio::select! {
+                e = stream.next() => match e {
+                    Some(e) => e,
+                    None => break
+                },
+                _ = self.stop.notified() => break
+            };
             match key_of(&e.entry).as_ref() {
-                CURRENT_STATUS_SETTING => {
-                    let s: Status = self.root_doc.read_blob_by_hash(e.entry.content_hash()).await?;
-                    broadcast(&self.bc, StatusSettingChanged(s))?;
-                }
+                CURRENT_STATUS_SETTING => match self.root_doc.read_entry::<Status>(&e.entry).await {
+                    Ok(Some(s)) => {
+                        broadcast(&self.bc, StatusSettingChanged(s))?;
+                    }
+                    Ok(None) => {}
+                    Err(err) => warn!(author = %e.entry.author(), "couldn't read changed status: {err:#}")
+                },
                 BIO => {
                     broadcast(&self.bc, OwnPublicBioUpdated(e.entry))?;
                 }
+                key if key.starts_with(&format!("{SETTINGS_PREFIX}/")) => {
+                    // local or from one of our paired devices, either way it changed
+                    if let Err(err) = self.setting_entry_inserted(&e.entry).await {
+                        warn!(%key, "couldn't read changed setting: {err:#}");
+                    }
+                }
                 _ => {}
             }
-        };
+        }
         Ok(())
     }
 
+    /// Ends doc_watch_loop, the root doc itself is closed by whoever shuts down the node
+    pub fn shutdown(&self) {
+        self.stop.notify_one();
+    }
+
     pub fn subscribe(&self) -> Receiver<SettingsEvent> {
         self.bc.subscribe()
     }
@@ -208,67 +412,175 @@ impl Service {
     }
 
     pub async fn set_status(&self, status: &Status) -> Result<()> {
-        self.root_doc.write_keyed_blob(CURRENT_STATUS_SETTING, &status).await?;
+        self.root_doc.write_keyed_blob(CURRENT_STATUS_SETTING, status.clone()).await?;
         Ok(())
     }
 
-    async fn get_settings_store(&self) -> Result<SettingsStore> {
-        let maybe_settings: Option<SettingsStore> = self.root_doc.read_own_blob(SETTINGS_STORE_KEY).await?;
-        Ok(match maybe_settings {
-            None => {
-                let new_settings = SettingsStore::new();
-                self.set_settings_store(&new_settings).await?;
-                new_settings
+    /// Settings used to live in a single blob, move them to one entry per key
+    async fn migrate_legacy_store(&self) -> Result<()> {
+        if let Some(legacy) = self.root_doc.read_own_blob::<SettingsStore>(SETTINGS_STORE_KEY).await? {
+            info!(count = legacy.0.len(), doc = %self.root_doc.id(), "migrating settings to per key entries");
+            for (store_key, value) in legacy.0 {
+                if self.read_store_value(&store_key).await?.is_none() {
+                    self.root_doc.write_keyed_blob(setting_key(&store_key).as_str(), value).await?;
+                }
             }
-            Some(s) => s
-        })
+            self.root_doc.del(self.root_doc.me().await, SETTINGS_STORE_KEY).await?;
+        }
+        Ok(())
+    }
+
+    async fn read_store_value(&self, store_key: &str) -> Result<Option<StoreValue>> {
+        self.root_doc.read_own_blob(setting_key(store_key).as_str()).await
+    }
+
+    fn store_key(&self, key: &str) -> String {
+        if is_device_local(key) {
+            format!("{key}/{}", self.root_doc.1.node_id())
+        } else {
+            String::from(key)
+        }
     }
 
-    async fn set_settings_store(&self, ss: &SettingsStore) -> Result<AddOutcome> {
-        self.root_doc.write_keyed_blob(SETTINGS_STORE_KEY, ss).await
+    /// The other way around, None for another device's local settings
+    fn logical_key<'a>(&self, store_key: &'a str) -> Option<&'a str> {
+        if is_device_local(store_key) {
+            store_key.strip_suffix(&format!("/{}", self.root_doc.1.node_id()))
+        } else {
+            Some(store_key)
+        }
     }
 
-    pub async fn bullshit(&self) -> Result<()> {
-        let mut ss: HashMap<String, StoreValue> = HashMap::new();
-        // ss.0.insert(SettingKey::ShareNearbyPublicBio, StoreValue::Bool(true));
-        ss.insert(String::from(SHARE_NEARBY_PUBLIC_BIO), StoreValue::Bool(true));
-        self.root_doc.write_keyed_blob(SETTINGS_STORE_KEY, &ss).await?;
-        let out: Option<HashMap<String, StoreValue>> = self.root_doc.read_own_blob(SETTINGS_STORE_KEY).await?;
-        let x = out.unwrap();
-        let z = x.get(SHARE_NEARBY_PUBLIC_BIO);
-        println!("{:?}", z);
+    /// So the first change after a launch has an old value to report
+    async fn seed_last_seen(&self) -> Result<()> {
+        let entries = self.root_doc.list_entries_by_query(
+            Query::author(self.root_doc.me().await).key_prefix(format!("{SETTINGS_PREFIX}/"))
+        ).await?;
+        let mut last_seen = self.last_seen.write().await;
+        for entry in entries {
+            let key = key_of(&entry);
+            let Some(logical) = self.logical_key(value_after(SETTINGS_PREFIX, &key)) else {
+                continue;
+            };
+            if entry.content_len() == 0 {
+                continue;
+            }
+            match self.root_doc.read_entry::<StoreValue>(&entry).await {
+                Ok(Some(value)) => {
+                    last_seen.insert(String::from(logical), value);
+                }
+                Ok(None) => {}
+                Err(e) => warn!(%key, "couldn't read stored setting: {e:#}")
+            }
+        }
         Ok(())
     }
 
+    async fn setting_entry_inserted(&self, entry: &Entry) -> Result<()> {
+        let key = key_of(entry);
+        let Some(logical) = self.logical_key(value_after(SETTINGS_PREFIX, &key)) else {
+            return Ok(());
+        };
+        if entry.content_len() == 0 {
+            // deleted, nothing to tell anyone
+            return Ok(());
+        }
+        let Some(new_value) = self.root_doc.read_entry::<StoreValue>(entry).await? else {
+            return Ok(());
+        };
+        let old_value = self.last_seen.write().await.insert(String::from(logical), new_value.clone());
+        if old_value.as_ref() != Some(&new_value) {
+            broadcast(&self.bc, SettingsEvent::SettingChanged(String::from(logical), old_value, new_value))?;
+        }
+        Ok(())
+    }
 
-    pub async fn get_setting<T: OptionValue>(&self, key: &str) -> Result<Option<T>> {
-        let ss = self.get_settings_store().await?;
-        let value: Option<T> = read_setting_from_store(&key, &ss);
+    /// Whatever is stored for key, device local or not, without defaults
+    pub(crate) async fn read_raw(&self, key: &str) -> Result<Option<StoreValue>> {
+        let mut value = self.read_store_value(&self.store_key(key)).await?;
+        if value.is_none() {
+            // from before device local settings were kept per node, or before
+            // the legacy store got migrated
+            value = self.read_store_value(key).await?;
+        }
+        if value.is_none() {
+            if let Some(legacy) = self.root_doc.read_own_blob::<SettingsStore>(SETTINGS_STORE_KEY).await? {
+                value = legacy.0.get(key).cloned();
+            }
+        }
         Ok(value)
     }
-    pub async fn get_setting_defaulted<T, F>(&self, key: &str, default: F) -> Result<T>
-    where
-        F: Fn() -> T,
-        T: OptionValue
-    {
-        let ss = self.get_settings_store().await?;
-        let value: Option<T> = read_setting_from_store(&key, &ss);
-
-        Ok(value.unwrap_or_else(default))
-    }
-
-    pub async fn set_setting<T>(&self, key: &str, value: T) -> Result<()>
-    where
-        T: OptionValue + Debug + Clone
-    {
-        let sv: StoreValue = value.into();
-        let new_value = sv.clone();
-        let mut ss = self.get_settings_store().await?;
-        let old_value: Option<StoreValue> = ss.0.get(key).cloned();
-
-        ss.0.insert(String::from(key), sv);
-        self.set_settings_store(&ss).await?;
-        broadcast(&self.bc, SettingsEvent::SettingChanged(String::from(key), old_value, new_value))?;
+
+    /// Only what's been set, None if it never was
+    pub async fn get_setting<T: OptionValue>(&self, setting: &Setting<T>) -> Result<Option<T>> {
+        match self.read_raw(setting.key).await? {
+            Some(v) => Ok(Some(setting.decode(v)?)),
+            None => Ok(None)
+        }
+    }
+
+    /// What's been set, or the setting's default
+    pub async fn get<T: OptionValue>(&self, setting: &Setting<T>) -> Result<T> {
+        match self.get_setting(setting).await? {
+            Some(v) => Ok(v),
+            None => setting.default_value().ok_or_else(|| GossipError::not_found(format!("setting {} isn't set and has no default", setting.key)).into())
+        }
+    }
+
+    pub async fn set<T: OptionValue>(&self, setting: &Setting<T>, value: T) -> Result<()> {
+        setting.check(&value)?;
+        self.put_setting(setting.key, value.into()).await
+    }
+
+    /// For the generic settings screen, which only knows keys from the registry
+    pub async fn get_value(&self, key: &str) -> Result<StoreValue> {
+        let setting = find_setting(key)?;
+        match self.read_raw(key).await? {
+            Some(v) => Ok(v),
+            None => setting.default_store_value().ok_or_else(|| GossipError::not_found(format!("setting {key} isn't set and has no default")).into())
+        }
+    }
+
+    pub async fn set_value(&self, key: &str, value: StoreValue) -> Result<()> {
+        find_setting(key)?.check_store_value(&value)?;
+        self.put_setting(key, value).await
+    }
+
+    /// Each setting is its own entry, so setting one never races with setting another,
+    /// and another device changing a different setting never undoes this one
+    pub(crate) async fn put_setting(&self, key: &str, new_value: StoreValue) -> Result<()> {
+        let store_key = self.store_key(key);
+        self.root_doc.write_keyed_blob(setting_key(&store_key).as_str(), new_value).await?;
+        // the doc watch loop tells everyone about it
+        Ok(())
+    }
+
+    /// Every setting as it is now, minus the ones that only mean anything on this device
+    pub async fn export_store(&self) -> Result<HashMap<String, StoreValue>> {
+        let mut values = HashMap::new();
+        let query = Query::author(self.root_doc.me().await).key_prefix(format!("{SETTINGS_PREFIX}/"));
+        for entry in self.root_doc.list_entries_by_query(query).await? {
+            let key = key_of(&entry);
+            let store_key = value_after(SETTINGS_PREFIX, &key);
+            if is_device_local(store_key) || entry.content_len() == 0 {
+                continue;
+            }
+            values.insert(String::from(store_key), self.root_doc.read_blob_by_hash(entry.content_hash()).await?);
+        }
+        Ok(values)
+    }
+
+    pub async fn import_store(&self, values: HashMap<String, StoreValue>) -> Result<()> {
+        for (key, value) in values {
+            if is_device_local(&key) {
+                continue;
+            }
+            if let Err(e) = find_setting(&key).and_then(|s| s.check_store_value(&value)) {
+                warn!(%key, "not importing setting: {e:#}");
+                continue;
+            }
+            self.put_setting(&key, value).await?;
+        }
         Ok(())
     }
 
@@ -278,16 +590,92 @@ impl Service {
 
 }
 
-fn read_setting_from_store<T: OptionValue>(key: &str, store: &SettingsStore) -> Option<T> {
-    let value: Option<StoreValue> = store.0.get(key).cloned();
-    let value: Option<T> = match value {
-        None => None,
-        Some(v) => {
-            v.into()
-        }
-    };
-    value
-}
 pub fn settings_file_path(base_path: impl AsRef<Path>) -> impl AsRef<Path> {
     base_path.as_ref().join(NODE_SETTINGS_FILE)
-}
\ No newline at end of file
+}
+
+#[test]
+pub fn store_values_from_before_the_enum_grew_still_decode() {
+    // StoreValue as it was first written to disk
+    #[derive(Serialize)]
+    enum OldStoreValue { Bool(bool), String(String), Int(i32) }
+    #[derive(Serialize)]
+    struct OldSettingsStore(HashMap<String, OldStoreValue>);
+
+    let mut old = OldSettingsStore(HashMap::new());
+    old.0.insert(String::from("a"), OldStoreValue::Bool(true));
+    old.0.insert(String::from("b"), OldStoreValue::String(String::from("hi")));
+    old.0.insert(String::from("c"), OldStoreValue::Int(7));
+    let mut s = flexbuffers::FlexbufferSerializer::new();
+    old.serialize(&mut s).unwrap();
+
+    let r = flexbuffers::Reader::get_root(s.view()).unwrap();
+    let decoded = SettingsStore::deserialize(r).unwrap();
+    assert_eq!(decoded.0.get("a"), Some(&StoreValue::Bool(true)));
+    assert_eq!(decoded.0.get("b"), Some(&StoreValue::String(String::from("hi"))));
+    assert_eq!(decoded.0.get("c"), Some(&StoreValue::Int(7)));
+}
+
+/// On a fresh node of its own, without the watch loop, so tests decide when
+/// entries get handled
+#[cfg(test)]
+pub(crate) async fn unwatched_service(dir: &Path) -> (crate::doc::Node, Service) {
+    let node = crate::doc::Node::persistent(dir).await.unwrap().spawn().await.unwrap();
+    let root_doc = Doc::new(node.docs().create().await.unwrap(), node.clone());
+    let s = Service(Arc::new(ServiceInner {
+        bc: create_broadcast(),
+        root_doc,
+        last_seen: RwLock::new(HashMap::new()),
+        stop: Notify::new(),
+    }));
+    (node, s)
+}
+
+#[test]
+pub fn reading_a_setting_doesnt_hide_its_change() {
+    let dir = std::env::temp_dir().join(format!("gossip-settings-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        // no watch loop, so the get lands before the entry is handled
+        let (node, s) = unwatched_service(&dir).await;
+        let mut sub = s.subscribe();
+
+        s.set(&SHARE_NEARBY_PUBLIC_BIO, true).await.unwrap();
+        assert!(s.get(&SHARE_NEARBY_PUBLIC_BIO).await.unwrap());
+        let key = setting_key(&s.store_key(SHARE_NEARBY_PUBLIC_BIO.key));
+        let entry = s.root_doc.get_exact(s.root_doc.me().await, key, false).await.unwrap().unwrap();
+        s.setting_entry_inserted(&entry).await.unwrap();
+
+        match sub.try_recv() {
+            Ok(SettingsEvent::SettingChanged(key, None, StoreValue::Bool(true))) => assert_eq!(key, SHARE_NEARBY_PUBLIC_BIO.key),
+            other => panic!("expected the change to be announced, got {other:?}")
+        }
+        node.shutdown().await.unwrap();
+    });
+}
+
+#[test]
+pub fn nested_store_values_round_trip() {
+    let blocked: Vec<WideId> = vec![[1u8; 32].into(), [2u8; 32].into()];
+    let mut windows: HashMap<String, Duration> = HashMap::new();
+    windows.insert(String::from("messages"), Duration::from_secs(60 * 60 * 24));
+
+    let values = vec![
+        StoreValue::from(blocked.clone()),
+        StoreValue::from(windows.clone()),
+        StoreValue::from(0.25f64),
+        StoreValue::from(i64::MAX),
+        StoreValue::from(SystemTime::UNIX_EPOCH),
+    ];
+    let mut s = flexbuffers::FlexbufferSerializer::new();
+    values.serialize(&mut s).unwrap();
+    let r = flexbuffers::Reader::get_root(s.view()).unwrap();
+    let decoded = Vec::<StoreValue>::deserialize(r).unwrap();
+    assert_eq!(decoded, values);
+
+    assert_eq!(Vec::<WideId>::from_store_value(decoded[0].clone()), Some(blocked));
+    assert_eq!(HashMap::<String, Duration>::from_store_value(decoded[1].clone()), Some(windows));
+    assert_eq!(Vec::<bool>::from_store_value(StoreValue::List(vec![StoreValue::Int(1)])), None);
+}
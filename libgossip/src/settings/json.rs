@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use iroh::client::blobs::BlobStatus;
+use iroh::docs::store::Query;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tracing::warn;
+
+use crate::blob_dispatcher::NamedBlob;
+use crate::data::{BlobHash, replace_or_add_blob};
+use crate::doc::key_of;
+use crate::nearby::model::Status;
+use crate::settings::Service;
+use crate::settings::registry::{all_settings, find_setting};
+use crate::views::errors::GossipError;
+
+// version 1 only had the hashes of the bio pics
+const SETTINGS_JSON_VERSION: u32 = 2;
+const BIO_TEXT: &str = "bio_text.txt";
+
+/// Everything about us a person might want to read or hand edit. Settings are
+/// keyed by their registry key and written as plain json values.
+#[derive(Serialize, Deserialize, Debug)]
+struct SettingsDocument {
+    version: u32,
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    bio: Option<BioDocument>,
+    #[serde(default)]
+    settings: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct BioDocument {
+    text: String,
+    // base64 of each pic, so a device that's never seen them gets them too
+    #[serde(default)]
+    pics: Vec<String>,
+}
+
+enum BioPic {
+    Data(Vec<u8>),
+    // from a version 1 document, only brought back if this device already has it
+    Known(BlobHash),
+}
+
+fn decode_pic(version: u32, pic: &str) -> Result<BioPic> {
+    if version < 2 {
+        Ok(BioPic::Known(pic.parse()?))
+    } else {
+        Ok(BioPic::Data(STANDARD.decode(pic)?))
+    }
+}
+
+impl Service {
+    pub async fn export_json(&self) -> Result<String> {
+        let mut settings = Map::new();
+        for setting in all_settings() {
+            if setting.is_internal() || setting.is_device_local() {
+                continue;
+            }
+            if let Some(value) = self.read_raw(setting.key()).await? {
+                settings.insert(String::from(setting.key()), setting.to_json(value)?);
+            }
+        }
+
+        let mut text = String::new();
+        let mut pics = vec![];
+        for blob in self.get_bio().await? {
+            if blob.name == BIO_TEXT {
+                text = self.root_doc.read_blob_by_hash(blob.hash.into()).await?;
+            } else {
+                let data = self.root_doc.blobs().read_to_bytes(blob.hash.into()).await?;
+                pics.push(STANDARD.encode(data));
+            }
+        }
+
+        let document = SettingsDocument {
+            version: SETTINGS_JSON_VERSION,
+            status: Some(self.get_status().await?.text),
+            bio: Some(BioDocument { text, pics }),
+            settings,
+        };
+        Ok(serde_json::to_string_pretty(&document)?)
+    }
+
+    /// Every setting and bio pic is checked before anything is written, so a document
+    /// with one bad value changes nothing
+    pub async fn import_json(&self, json: &str) -> Result<()> {
+        let document: SettingsDocument = serde_json::from_str(json)?;
+        if document.version > SETTINGS_JSON_VERSION {
+            return Err(GossipError::invalid_input(format!("settings json is version {}, this build only understands up to {SETTINGS_JSON_VERSION}", document.version)).into());
+        }
+
+        let mut values = vec![];
+        let mut problems = vec![];
+        for (key, value) in document.settings.iter() {
+            let checked = find_setting(key).and_then(|setting| {
+                if setting.is_internal() || setting.is_device_local() {
+                    Err(GossipError::invalid_input(format!("setting {key} can't be imported")).into())
+                } else {
+                    setting.parse_json(value)
+                }
+            });
+            match checked {
+                Ok(v) => values.push((key.clone(), v)),
+                Err(e) => problems.push(e.to_string())
+            }
+        }
+        let bio = match document.bio {
+            Some(bio) => {
+                let mut pics = vec![];
+                for (i, pic) in bio.pics.iter().take(9).enumerate() {
+                    match decode_pic(document.version, pic) {
+                        Ok(p) => pics.push(p),
+                        Err(e) => problems.push(format!("bio pic {i}: {e}"))
+                    }
+                }
+                Some((bio.text, pics))
+            }
+            None => None
+        };
+        if !problems.is_empty() {
+            return Err(GossipError::invalid_input(format!("settings json has problems: {}", problems.join("; "))).into());
+        }
+
+        for (key, value) in values {
+            self.put_setting(&key, value).await?;
+        }
+        if let Some(text) = document.status {
+            self.set_status(&Status { text }).await?;
+        }
+        if let Some((text, pics)) = bio {
+            self.import_bio(text, pics).await?;
+        }
+        Ok(())
+    }
+
+    /// Every setting, internal ones too, and how many entries of each kind the root
+    /// doc holds. Only settings are read, the doc's other entries include our
+    /// identity secrets so they're just counted.
+    pub async fn diagnostics_json(&self) -> Result<Value> {
+        let mut settings = Map::new();
+        for setting in all_settings() {
+            let value = match self.read_raw(setting.key()).await {
+                Ok(Some(value)) => setting.to_json(value).unwrap_or_else(|e| Value::String(format!("unreadable: {e}"))),
+                Ok(None) => Value::Null,
+                Err(e) => Value::String(format!("unreadable: {e}"))
+            };
+            settings.insert(String::from(setting.key()), value);
+        }
+
+        let mut entries: BTreeMap<String, usize> = BTreeMap::new();
+        for entry in self.root_doc.list_entries_by_query(Query::all()).await? {
+            let key = key_of(&entry);
+            // drop the pk or doc id on the end, it's the kind of entry that's interesting
+            let kind: Vec<&str> = key.split('/').take(2).collect();
+            *entries.entry(kind.join("/")).or_default() += 1;
+        }
+
+        Ok(json!({
+            "root_doc": self.root_doc.id().to_string(),
+            "settings": settings,
+            "root_doc_entries": entries,
+        }))
+    }
+
+    async fn import_bio(&self, text: String, pics: Vec<BioPic>) -> Result<()> {
+        let mut blobs: Vec<NamedBlob> = vec![];
+        let text = self.root_doc.write_blob(text).await?;
+        replace_or_add_blob(BIO_TEXT, text.hash.into(), &mut blobs).await;
+
+        for (i, pic) in pics.into_iter().enumerate() {
+            let name = format!("{i}.png");
+            match pic {
+                BioPic::Data(data) => {
+                    let added = self.root_doc.blobs().add_bytes(data).await?;
+                    blobs.push(NamedBlob { name, hash: added.hash.into() });
+                }
+                BioPic::Known(hash) => match self.root_doc.blobs().status(hash.into()).await? {
+                    BlobStatus::Complete { .. } => blobs.push(NamedBlob { name, hash }),
+                    _ => warn!(%hash, "skipping bio pic, this device doesn't have it")
+                }
+            }
+        }
+        self.set_bio(blobs).await
+    }
+}
+
+#[test]
+pub fn bio_pics_come_along_to_a_fresh_device() {
+    let dir = std::env::temp_dir().join(format!("gossip-settings-json-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let (node_a, a) = crate::settings::unwatched_service(&dir.join("a")).await;
+        let (node_b, b) = crate::settings::unwatched_service(&dir.join("b")).await;
+        let pic = node_a.blobs().add_bytes(vec![1u8, 2, 3]).await.unwrap();
+        a.set_bio(vec![NamedBlob { name: String::from("0.png"), hash: pic.hash.into() }]).await.unwrap();
+        a.set_status(&Status { text: String::from("testing") }).await.unwrap();
+        let json = a.export_json().await.unwrap();
+
+        b.import_json(&json).await.unwrap();
+        assert_eq!(b.get_status().await.unwrap().text, "testing");
+        let bio = b.get_bio().await.unwrap();
+        let imported = bio.iter().find(|blob| blob.name != BIO_TEXT).unwrap();
+        assert_eq!(node_b.blobs().read_to_bytes(imported.hash.into()).await.unwrap().as_ref(), [1u8, 2, 3]);
+
+        // a pic that doesn't decode stops the status being written too
+        let bad = r#"{"version": 2, "status": "half applied", "bio": {"text": "", "pics": ["not base64!"]}}"#;
+        assert!(b.import_json(bad).await.is_err());
+        let bad_hash = r#"{"version": 1, "status": "half applied", "bio": {"text": "", "pics": ["not a hash"]}}"#;
+        assert!(b.import_json(bad_hash).await.is_err());
+        assert_eq!(b.get_status().await.unwrap().text, "testing");
+
+        node_a.shutdown().await.unwrap();
+        node_b.shutdown().await.unwrap();
+    });
+}
@@ -0,0 +1,170 @@
+use std::marker::PhantomData;
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::data::WideId;
+use crate::settings::{OptionValue, StoreValue};
+use crate::views::errors::GossipError;
+
+#[derive(uniffi::Enum, Clone, Copy, Debug, PartialEq)]
+pub enum SettingKind {
+    Bool, String, Int, WideId, Float, Long, Duration, Timestamp, List, Map
+}
+
+/// What the app needs to render a setting without knowing about it ahead of time
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct SettingDescriptor {
+    pub key: String,
+    pub kind: SettingKind,
+    pub default: Option<StoreValue>,
+    pub description: String,
+}
+
+/// A setting and everything we know about it. Settings are read and written
+/// through these, never through their bare key.
+pub struct Setting<T> {
+    pub key: &'static str,
+    pub description: &'static str,
+    // internal settings have no default, reading one that isn't set gives None
+    pub default: Option<fn() -> T>,
+    pub validate: fn(&T) -> Result<(), String>,
+    // kept per node, since the root doc is shared between all of a user's devices
+    pub device_local: bool,
+    // not shown on the generic settings screen
+    pub internal: bool,
+    _t: PhantomData<T>,
+}
+
+fn anything<T>(_: &T) -> Result<(), String> { Ok(()) }
+
+impl<T: OptionValue> Setting<T> {
+    const fn new(key: &'static str, description: &'static str, default: fn() -> T) -> Self {
+        Setting { key, description, default: Some(default), validate: anything, device_local: false, internal: false, _t: PhantomData }
+    }
+
+    const fn internal(key: &'static str, description: &'static str) -> Self {
+        Setting { key, description, default: None, validate: anything, device_local: false, internal: true, _t: PhantomData }
+    }
+
+    const fn device_local(mut self) -> Self {
+        self.device_local = true;
+        self
+    }
+
+    pub fn default_value(&self) -> Option<T> {
+        self.default.map(|d| d())
+    }
+
+    /// Turn whatever was stored into a T, complaining instead of pretending it wasn't there
+    pub fn decode(&self, value: StoreValue) -> Result<T> {
+        let shown = format!("{value:?}");
+        T::from_store_value(value)
+            .ok_or_else(|| GossipError::invalid_input(format!("setting {} holds {shown}, expected a {:?}", self.key, T::KIND)).into())
+    }
+
+    pub fn check(&self, value: &T) -> Result<()> {
+        (self.validate)(value).map_err(|reason| GossipError::invalid_input(format!("invalid value for setting {}: {reason}", self.key)).into())
+    }
+}
+
+/// Setting with the type erased, so they can all live in one list
+pub trait AnySetting: Sync {
+    fn key(&self) -> &'static str;
+    fn is_device_local(&self) -> bool;
+    fn is_internal(&self) -> bool;
+    fn descriptor(&self) -> SettingDescriptor;
+    fn default_store_value(&self) -> Option<StoreValue>;
+    fn check_store_value(&self, value: &StoreValue) -> Result<()>;
+    fn to_json(&self, value: StoreValue) -> Result<Value>;
+    fn parse_json(&self, value: &Value) -> Result<StoreValue>;
+}
+
+impl<T: OptionValue> AnySetting for Setting<T> {
+    fn key(&self) -> &'static str { self.key }
+    fn is_device_local(&self) -> bool { self.device_local }
+    fn is_internal(&self) -> bool { self.internal }
+
+    fn descriptor(&self) -> SettingDescriptor {
+        SettingDescriptor {
+            key: String::from(self.key),
+            kind: T::KIND,
+            default: self.default_store_value(),
+            description: String::from(self.description),
+        }
+    }
+
+    fn default_store_value(&self) -> Option<StoreValue> {
+        self.default_value().map(|d| d.into())
+    }
+
+    fn check_store_value(&self, value: &StoreValue) -> Result<()> {
+        let decoded = self.decode(value.clone())?;
+        self.check(&decoded)
+    }
+
+    fn to_json(&self, value: StoreValue) -> Result<Value> {
+        Ok(T::to_json(&self.decode(value)?))
+    }
+
+    fn parse_json(&self, value: &Value) -> Result<StoreValue> {
+        let decoded = T::from_json(value)
+            .ok_or_else(|| GossipError::invalid_input(format!("setting {} can't be {value}, expected a {:?}", self.key, T::KIND)))?;
+        self.check(&decoded)?;
+        Ok(decoded.into())
+    }
+}
+
+pub const SHARE_NEARBY_PUBLIC_BIO: Setting<bool> = Setting::new(
+    "share_nearby_public_bio",
+    "Show your bio to people in nearby groups",
+    || false
+);
+
+pub const ANONYMOUS_IN_NEW_GROUPS: Setting<bool> = Setting::new(
+    "anonymous_in_new_groups",
+    "Join new groups as a throwaway identity instead of your own",
+    || false
+);
+
+pub const CURRENT_NEARBY_DOC_ID: Setting<WideId> = Setting::internal(
+    "current_nearby_doc_id",
+    "The nearby group doc to reopen on launch"
+).device_local();
+
+pub const KEEP_CONNECTION_TIMELINE: Setting<bool> = Setting::new(
+    "keep_connection_timeline",
+    "Save the connection timeline so it survives restarts, for tracking down connection problems",
+    || false
+).device_local();
+
+static REGISTRY: [&dyn AnySetting; 4] = [
+    &SHARE_NEARBY_PUBLIC_BIO,
+    &ANONYMOUS_IN_NEW_GROUPS,
+    &CURRENT_NEARBY_DOC_ID,
+    &KEEP_CONNECTION_TIMELINE,
+];
+
+pub fn all_settings() -> &'static [&'static dyn AnySetting] {
+    &REGISTRY
+}
+
+pub fn find_setting(key: &str) -> Result<&'static dyn AnySetting> {
+    REGISTRY.iter().copied().find(|s| s.key() == key)
+        .ok_or_else(|| GossipError::not_found(format!("there's no setting called {key}")).into())
+}
+
+/// Everything the app is allowed to show and change
+#[uniffi::export]
+pub fn settings_registry() -> Vec<SettingDescriptor> {
+    REGISTRY.iter().filter(|s| !s.is_internal()).map(|s| s.descriptor()).collect()
+}
+
+#[test]
+pub fn registry_rejects_mismatched_types() {
+    let share = find_setting(SHARE_NEARBY_PUBLIC_BIO.key).unwrap();
+    assert!(share.check_store_value(&StoreValue::Bool(true)).is_ok());
+    assert!(share.check_store_value(&StoreValue::Int(1)).is_err());
+    assert!(find_setting("share_nearby_pubic_bio").is_err());
+    assert!(settings_registry().iter().all(|d| d.key != CURRENT_NEARBY_DOC_ID.key));
+}
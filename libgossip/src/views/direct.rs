@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use anyhow::Error;
+use async_trait::async_trait;
+use tokio::sync::Notify;
+
+use crate::data::PublicKey;
+use crate::direct::DirectService;
+use crate::direct::model::ConversationEvents;
+use crate::events::{Delivery, recv_event, start_with, Starter};
+use crate::nearby::model::DisplayMessage;
+use crate::views::errors::GossipError;
+
+#[uniffi::export(with_foreign)]
+#[async_trait]
+pub trait DirectConversationViewModel: Send + Sync + 'static {
+    async fn name_updated(&self, name: String);
+
+    async fn all_messages_updated(&self, messages: Vec<DisplayMessage>);
+
+    async fn received_one_message(&self, message: DisplayMessage);
+}
+
+#[derive(uniffi::Object, Clone)]
+pub struct DirectConversationViewController {
+    subject_pk: PublicKey,
+    direct_service: DirectService,
+    view_model: Arc<dyn DirectConversationViewModel>,
+    stop: Arc<Notify>
+}
+
+impl DirectConversationViewController {
+    pub fn new(subject_pk: PublicKey, view_model: Arc<dyn DirectConversationViewModel>, direct_service: DirectService) -> Arc<Self> {
+        Arc::new(start_with(DirectConversationViewController {
+            subject_pk,
+            direct_service,
+            view_model,
+            stop: Arc::new(Notify::new())
+        }))
+    }
+}
+
+impl Drop for DirectConversationViewController {
+    fn drop(&mut self) {
+        self.stop.notify_waiters();
+    }
+}
+
+#[async_trait]
+impl Starter for DirectConversationViewController {
+    async fn start(&self) -> Result<(), Error> {
+        let conversation = self.direct_service.get_conversation(&self.subject_pk).await?;
+        let (messages, mut sub) = conversation.subscribe_with_snapshot().await?;
+        self.view_model.name_updated(conversation.record().name.clone()).await;
+        self.view_model.all_messages_updated(messages).await;
+
+        let mut listen = true;
+        while listen {
+            tokio::select! {
+                d = recv_event(&mut sub) => {
+                    match d {
+                        Delivery::Event(ConversationEvents::AllMessagesUpdated(msgs)) => {
+                            self.view_model.all_messages_updated(msgs).await;
+                        }
+                        Delivery::Event(ConversationEvents::ReceivedOneMessage(msg)) => {
+                            self.view_model.received_one_message(msg).await;
+                        }
+                        Delivery::Resync => {
+                            self.view_model.all_messages_updated(conversation.all_messages().await).await;
+                        }
+                        Delivery::Closed => {
+                            listen = false;
+                        }
+                    }
+                }
+                _ = self.stop.notified() => {
+                    listen = false;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl DirectConversationViewController {
+    pub async fn send_message(&self, text: String) -> Result<(), GossipError> {
+        let conversation = self.direct_service.get_conversation(&self.subject_pk).await?;
+        conversation.send_message(text).await?;
+        Ok(())
+    }
+
+    pub async fn start_sync(&self) -> Result<(), GossipError> {
+        let conversation = self.direct_service.get_conversation(&self.subject_pk).await?;
+        conversation.start_sync().await?;
+        Ok(())
+    }
+}
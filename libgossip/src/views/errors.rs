@@ -1,10 +1,86 @@
-#[derive(Debug, thiserror::Error, uniffi::Object)]
-#[error("{err:?}")]
-pub struct GossipError {
-    err: anyhow::Error
+use crate::identity::backup::BackupError;
+use crate::identity::model::IdentityClaimError;
+use crate::record::RecordError;
+
+/// What the app gets back when a call fails. Services return these inside their
+/// anyhow errors where they know what went wrong, anything else is Internal.
+#[derive(Debug, Clone, thiserror::Error, uniffi::Error)]
+pub enum GossipError {
+    /// Not set up for this yet, eg. no group doc loaded. Usually worth retrying later
+    #[error("not ready: {reason}")]
+    NotReady { reason: String },
+    #[error("not found: {reason}")]
+    NotFound { reason: String },
+    /// Blob data we need isn't on this device (yet)
+    #[error("blob missing: {reason}")]
+    BlobMissing { reason: String },
+    /// Couldn't reach or sync with a peer
+    #[error("network: {reason}")]
+    Network { reason: String },
+    /// The caller asked for something that can't be done, showing the reason is fine
+    #[error("invalid input: {reason}")]
+    InvalidInput { reason: String },
+    #[error("permission denied: {reason}")]
+    PermissionDenied { reason: String },
+    #[error("internal: {reason}")]
+    Internal { reason: String },
 }
+
+impl GossipError {
+    pub fn not_ready(reason: impl Into<String>) -> Self {
+        GossipError::NotReady { reason: reason.into() }
+    }
+    pub fn not_found(reason: impl Into<String>) -> Self {
+        GossipError::NotFound { reason: reason.into() }
+    }
+    pub fn blob_missing(reason: impl Into<String>) -> Self {
+        GossipError::BlobMissing { reason: reason.into() }
+    }
+    pub fn network(reason: impl Into<String>) -> Self {
+        GossipError::Network { reason: reason.into() }
+    }
+    pub fn invalid_input(reason: impl Into<String>) -> Self {
+        GossipError::InvalidInput { reason: reason.into() }
+    }
+    pub fn permission_denied(reason: impl Into<String>) -> Self {
+        GossipError::PermissionDenied { reason: reason.into() }
+    }
+}
+
 impl From<anyhow::Error> for GossipError {
     fn from(err: anyhow::Error) -> Self {
-        Self { err }
+        let err = match err.downcast::<GossipError>() {
+            Ok(e) => return e,
+            Err(err) => err
+        };
+        if let Some(e) = err.downcast_ref::<BackupError>() {
+            return match e {
+                BackupError::WrongPassword => GossipError::permission_denied(e.to_string()),
+                BackupError::Malformed | BackupError::UnsupportedVersion(_) => GossipError::invalid_input(e.to_string()),
+                _ => GossipError::Internal { reason: e.to_string() }
+            };
+        }
+        if let Some(e) = err.downcast_ref::<IdentityClaimError>() {
+            return GossipError::permission_denied(e.to_string());
+        }
+        if let Some(e) = err.downcast_ref::<RecordError>() {
+            return GossipError::invalid_input(e.to_string());
+        }
+        if err.downcast_ref::<serde_json::Error>().is_some() {
+            return GossipError::invalid_input(format!("{err:#}"));
+        }
+        GossipError::Internal { reason: format!("{err:?}") }
     }
-}
\ No newline at end of file
+}
+
+#[test]
+pub fn errors_keep_their_kind_through_anyhow() {
+    let e: anyhow::Error = GossipError::not_ready("no group doc loaded yet").into();
+    assert!(matches!(GossipError::from(e), GossipError::NotReady { .. }));
+
+    let e: anyhow::Error = BackupError::WrongPassword.into();
+    assert!(matches!(GossipError::from(e), GossipError::PermissionDenied { .. }));
+
+    let e = anyhow::anyhow!("wtf");
+    assert!(matches!(GossipError::from(e), GossipError::Internal { .. }));
+}
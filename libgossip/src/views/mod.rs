@@ -4,18 +4,24 @@ use anyhow::{Error, Result};
 use async_trait::async_trait;
 
 use crate::blob_dispatcher::LoadCollectionDelegate;
-use crate::data::{BlobHash, WideId};
-use crate::events::{start_with, Starter};
+use crate::contacts::ContactsService;
+use crate::contacts::model::{Contact, ContactsServiceEvents};
+use crate::data::{BlobHash, PublicKey, WideId};
+use crate::direct::DirectService;
+use crate::direct::model::{ConversationRequest, ConversationSummary, DirectServiceEvents};
+use crate::events::{Delivery, recv_event, report_error, start_with, Starter, subscribe_errors};
 use crate::identity::IdentityService;
 use crate::identity::model::{Identity, IdentityServiceEvents};
 use crate::nearby::{NearbyService, NearbyServiceEvents};
 use crate::nearby::model::{ConState, DocData, DisplayMessage, NearbyProfile, Status};
-use crate::settings::{SettingsEvent, SettingsService};
+use crate::settings::{ANONYMOUS_IN_NEW_GROUPS, SettingsEvent, SettingsService, StoreValue};
 use crate::views::errors::GossipError;
 
 pub mod errors;
 pub mod node_stat;
 pub mod nearby_details;
+pub mod direct;
+pub mod timeline;
 
 #[uniffi::export(with_foreign)]
 #[async_trait]
@@ -34,6 +40,20 @@ pub trait GlobalViewModel: Send + Sync + 'static {
     async fn all_messages_updated(&self, messages: Vec<DisplayMessage>);
 
     async fn received_one_message(&self, message: DisplayMessage);
+
+    async fn contacts_updated(&self, contacts: Vec<Contact>);
+
+    async fn contact_nearby(&self, contact: Contact);
+
+    async fn direct_conversations_updated(&self, conversations: Vec<ConversationSummary>);
+
+    /// Requests waiting for accept_direct_conversation or decline_direct_conversation
+    async fn direct_conversation_requests_updated(&self, requests: Vec<ConversationRequest>);
+
+    async fn identities_updated(&self, identities: Vec<Identity>);
+
+    /// Something failed in the background, where there was no call to return it from
+    async fn background_error(&self, error: GossipError);
 }
 
 #[derive(uniffi::Object, Clone)]
@@ -41,15 +61,19 @@ pub struct Global {
     identity_service: IdentityService,
     nearby_service: NearbyService,
     settings_service: SettingsService,
+    contacts_service: ContactsService,
+    direct_service: DirectService,
     view_model: Arc<dyn GlobalViewModel>
 }
 impl Global {
-    pub fn new(view_model: Arc<dyn GlobalViewModel>, identity_service: IdentityService, nearby_service: NearbyService, settings_service: SettingsService) -> Arc<Self> {
+    pub fn new(view_model: Arc<dyn GlobalViewModel>, identity_service: IdentityService, nearby_service: NearbyService, settings_service: SettingsService, contacts_service: ContactsService, direct_service: DirectService) -> Arc<Self> {
         Arc::new(start_with(Global {
             identity_service,
             nearby_service,
             view_model,
-            settings_service
+            settings_service,
+            contacts_service,
+            direct_service
         }))
     }
 }
@@ -57,81 +81,158 @@ impl Global {
 #[async_trait]
 impl Starter for Global {
     async fn start(&self) -> std::result::Result<(), Error> {
-        self.view_model.status_updated(self.settings_service.get_status().await?).await;
-        self.view_model.connection_state_updated(self.nearby_service.get_con_state().await).await;
-        if let Some(iden) = self.identity_service.get_default_identity().await? {
-            self.view_model.own_public_key_updated(iden.pk).await;
-            self.view_model.name_updated(iden.name).await;
-            if let Some((pic,_)) = self.identity_service.get_pic(iden.pk).await? {
-                self.view_model.pic_updated(pic).await;
-            }
-        }
-        self.view_model.broadcasting_updated(self.nearby_service.should_broadcast().await).await;
-
-
-        self.listen().await;
-        Ok(())
-    }
-}
-
-impl Global {
-
-    async fn listen(&self) {
+        // subscribed before anything is pushed, so nothing in between goes missing
         let mut iden_sub = self.identity_service.subscribe();
         let mut nearby_sub = self.nearby_service.subscribe();
         let mut settings_sub = self.settings_service.subscribe();
+        let mut errors_sub = subscribe_errors();
+        let (contacts, mut contacts_sub) = self.contacts_service.subscribe_with_snapshot().await?;
+        let (conversations, mut direct_sub) = self.direct_service.subscribe_with_snapshot().await?;
 
+        self.push_snapshot().await?;
+        self.view_model.contacts_updated(contacts).await;
+        self.view_model.direct_conversations_updated(conversations).await;
+        self.view_model.direct_conversation_requests_updated(self.direct_service.list_requests().await).await;
         self.nearby_service.broadcast_doc_data().await;
-        self.nearby_service.broadcast_all_messages().await.expect("broadcast all messages");
+        if let Err(e) = self.nearby_service.broadcast_all_messages().await {
+            report_error("couldn't load messages", e);
+        }
 
-        let mut listen = true;
-        while listen {
+        loop {
             tokio::select! {
-                Ok(e) = settings_sub.recv() => {
-                    match e {
-                        SettingsEvent::StatusSettingChanged(s) => {
-                            self.view_model.status_updated(s).await;
-                        }
-                        SettingsEvent::OwnPublicBioUpdated(_) => {}
-                        SettingsEvent::SettingChanged(_,_,_) => {}}
-                }
-                Ok(e) = iden_sub.recv() => {
-                    match e {
-                        IdentityServiceEvents::DefaultIdentityUpdated(i) => {
-                            self.view_model.name_updated(i.name).await;
-                        }
-                        IdentityServiceEvents::DefaultIdentityPicUpdated(hash, _) => {
-                            self.view_model.pic_updated(hash).await;
-                        }
+                d = recv_event(&mut settings_sub) => match d {
+                    Delivery::Event(e) => self.settings_event(e).await,
+                    Delivery::Resync => self.resync(self.push_snapshot().await),
+                    Delivery::Closed => break
+                },
+                d = recv_event(&mut iden_sub) => match d {
+                    Delivery::Event(e) => self.identity_event(e).await,
+                    Delivery::Resync => self.resync(self.push_snapshot().await),
+                    Delivery::Closed => break
+                },
+                d = recv_event(&mut nearby_sub) => match d {
+                    Delivery::Event(e) => self.nearby_event(e).await,
+                    Delivery::Resync => self.resync(self.nearby_service.broadcast_snapshot().await),
+                    Delivery::Closed => break
+                },
+                d = recv_event(&mut contacts_sub) => match d {
+                    Delivery::Event(ContactsServiceEvents::ContactsUpdated(contacts)) => {
+                        self.view_model.contacts_updated(contacts).await;
                     }
-                }
-                Ok(e) = nearby_sub.recv() => {
-                    match e {
-                        NearbyServiceEvents::BroadcastingUpdated(val) => {
-                            self.view_model.broadcasting_updated(val).await;
-                        },
-                        NearbyServiceEvents::IdentitiesUpdated(idens) => {
-                            self.view_model.nearby_profiles_updated(idens).await;
-                        },
-                        NearbyServiceEvents::DocDataUpdated(state) => {
-                            self.view_model.doc_data_updated(state).await;
-                        },
-                        NearbyServiceEvents::AllMessagesUpdated(msgs) => {
-                            self.view_model.all_messages_updated(msgs).await;
-                        }
-                        NearbyServiceEvents::ReceivedOneNewMessage(msg) => {
-                            self.view_model.received_one_message(msg).await;
-                        },
-                        NearbyServiceEvents::ConStateUpdated(c) => {
-                            self.view_model.connection_state_updated(c).await;
-                        }
-                        _ => {}
+                    Delivery::Resync => match self.contacts_service.list_contacts().await {
+                        Ok(contacts) => self.view_model.contacts_updated(contacts).await,
+                        Err(e) => self.resync(Err(e))
+                    },
+                    Delivery::Closed => break
+                },
+                d = recv_event(&mut direct_sub) => match d {
+                    Delivery::Event(DirectServiceEvents::ConversationsUpdated(conversations)) => {
+                        self.view_model.direct_conversations_updated(conversations).await;
+                    }
+                    Delivery::Event(DirectServiceEvents::RequestsUpdated(requests)) => {
+                        self.view_model.direct_conversation_requests_updated(requests).await;
                     }
+                    Delivery::Resync => {
+                        self.view_model.direct_conversations_updated(self.direct_service.list_conversations().await).await;
+                        self.view_model.direct_conversation_requests_updated(self.direct_service.list_requests().await).await;
+                    }
+                    Delivery::Closed => break
+                },
+                d = recv_event(&mut errors_sub) => match d {
+                    Delivery::Event(e) => self.view_model.background_error(e).await,
+                    // errors that were dropped are still in the log
+                    Delivery::Resync => {}
+                    Delivery::Closed => break
                 },
-                else => {
-                    listen = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Global {
+
+    /// Our own side of things, everything that doesn't come from the nearby group
+    async fn push_snapshot(&self) -> Result<()> {
+        self.view_model.status_updated(self.settings_service.get_status().await?).await;
+        self.view_model.connection_state_updated(self.nearby_service.get_con_state().await).await;
+        if let Some(iden) = self.identity_service.get_active_identity().await? {
+            self.active_identity_updated(iden).await?;
+        }
+        self.view_model.identities_updated(self.identity_service.list_identities().await?).await;
+        self.view_model.broadcasting_updated(self.nearby_service.should_broadcast().await).await;
+        Ok(())
+    }
+
+    fn resync(&self, r: Result<()>) {
+        if let Err(e) = r {
+            report_error("couldn't resync after missing events", e);
+        }
+    }
+
+    async fn active_identity_updated(&self, iden: Identity) -> Result<()> {
+        self.view_model.own_public_key_updated(iden.pk).await;
+        self.view_model.name_updated(iden.name).await;
+        if let Some((pic,_)) = self.identity_service.get_pic(iden.pk).await? {
+            self.view_model.pic_updated(pic).await;
+        }
+        Ok(())
+    }
+
+    async fn settings_event(&self, e: SettingsEvent) {
+        match e {
+            SettingsEvent::StatusSettingChanged(s) => {
+                self.view_model.status_updated(s).await;
+            }
+            SettingsEvent::OwnPublicBioUpdated(_) => {}
+            SettingsEvent::SettingChanged(_,_,_) => {}
+        }
+    }
+
+    async fn identity_event(&self, e: IdentityServiceEvents) {
+        match e {
+            IdentityServiceEvents::DefaultIdentityUpdated(i) => {
+                self.view_model.name_updated(i.name).await;
+            }
+            IdentityServiceEvents::DefaultIdentityPicUpdated(hash, _) => {
+                self.view_model.pic_updated(hash).await;
+            }
+            IdentityServiceEvents::ActiveIdentityChanged(i) => {
+                if let Err(e) = self.active_identity_updated(i).await {
+                    report_error("couldn't show switched identity", e);
                 }
             }
+            IdentityServiceEvents::IdentitiesUpdated(idens) => {
+                self.view_model.identities_updated(idens).await;
+            }
+            _ => {}
+        }
+    }
+
+    async fn nearby_event(&self, e: NearbyServiceEvents) {
+        match e {
+            NearbyServiceEvents::BroadcastingUpdated(val) => {
+                self.view_model.broadcasting_updated(val).await;
+            },
+            NearbyServiceEvents::IdentitiesUpdated(idens) => {
+                self.view_model.nearby_profiles_updated(idens).await;
+            },
+            NearbyServiceEvents::DocDataUpdated(state) => {
+                self.view_model.doc_data_updated(state).await;
+            },
+            NearbyServiceEvents::AllMessagesUpdated(msgs) => {
+                self.view_model.all_messages_updated(msgs).await;
+            }
+            NearbyServiceEvents::ReceivedOneNewMessage(msg) => {
+                self.view_model.received_one_message(msg).await;
+            },
+            NearbyServiceEvents::ConStateUpdated(c) => {
+                self.view_model.connection_state_updated(c).await;
+            }
+            NearbyServiceEvents::ContactNearby(contact) => {
+                self.view_model.contact_nearby(contact).await;
+            }
+            _ => {}
         }
     }
 }
@@ -149,13 +250,15 @@ impl Global {
         Ok(())
     }
 
-    pub async fn set_status(&self, status: String) {
+    pub async fn set_status(&self, status: String) -> Result<(), GossipError> {
         let s = Status { text: status };
-        self.settings_service.set_status(&s).await.expect("set status")
+        self.settings_service.set_status(&s).await?;
+        Ok(())
     }
 
-    pub async fn send_message(&self, text: String, payload_dir: Option<String>) {
-        self.nearby_service.post_message(text, payload_dir).await.expect("send message");
+    pub async fn send_message(&self, text: String, payload_dir: Option<String>) -> Result<(), GossipError> {
+        self.nearby_service.post_message(text, payload_dir).await?;
+        Ok(())
     }
 
     pub async fn start_sync(&self) -> Result<(), GossipError> {
@@ -173,11 +276,12 @@ impl Global {
         Ok(())
     }
 
-    pub async fn load_nearby_payload(&self, hash: BlobHash, collection_delegate: Arc<dyn LoadCollectionDelegate>) {
-        self.nearby_service.get_or_download_collection(hash, collection_delegate).await.expect("downloading collection")
+    pub async fn load_nearby_payload(&self, hash: BlobHash, collection_delegate: Arc<dyn LoadCollectionDelegate>) -> Result<(), GossipError> {
+        self.nearby_service.get_or_download_collection(hash, collection_delegate).await?;
+        Ok(())
     }
     pub async fn set_name(&self, name: String) -> Result<(), GossipError> {
-        let pk = self.identity_service.get_default_identity_pk().await?;
+        let pk = self.identity_service.get_active_identity_pk().await?;
         let iden = self.identity_service.get_identity(pk).await?;
         let iden_to_save = match iden {
             None => {
@@ -193,9 +297,93 @@ impl Global {
         Ok(())
     }
 
+    pub async fn save_contact(&self, pk: PublicKey, note: String) -> Result<Contact, GossipError> {
+        let profile = self.nearby_service.get_profile_by_key(&pk).await?;
+        Ok(self.contacts_service.save_contact(&profile, note).await?)
+    }
+
+    pub async fn remove_contact(&self, pk: PublicKey) -> Result<(), GossipError> {
+        self.contacts_service.remove_contact(&pk).await?;
+        Ok(())
+    }
+
+    pub async fn set_contact_verified(&self, pk: PublicKey, verified: bool) -> Result<(), GossipError> {
+        self.contacts_service.set_verified(&pk, verified).await?;
+        Ok(())
+    }
+
+    pub async fn set_contact_note(&self, pk: PublicKey, note: String) -> Result<(), GossipError> {
+        self.contacts_service.set_note(&pk, note).await?;
+        Ok(())
+    }
+
+    pub async fn list_contacts(&self) -> Result<Vec<Contact>, GossipError> {
+        Ok(self.contacts_service.list_contacts().await?)
+    }
+
+    pub async fn start_direct_conversation(&self, pk: PublicKey) -> Result<ConversationSummary, GossipError> {
+        let profile = self.nearby_service.get_profile_by_key(&pk).await?;
+        let peer_node = self.nearby_service.get_node_id(&pk).await?
+            .ok_or_else(|| GossipError::network(format!("{} hasn't published a node to reach them at", profile.name)))?;
+        let me = self.nearby_service.persona().await?;
+        let summary = self.direct_service.start_conversation(pk, profile.name, peer_node, me).await?;
+        self.nearby_service.request_direct_conversation(&pk).await?;
+        Ok(summary)
+    }
+
+    pub async fn accept_direct_conversation(&self, pk: PublicKey) -> Result<ConversationSummary, GossipError> {
+        Ok(self.direct_service.accept_request(&pk).await?)
+    }
+
+    pub async fn decline_direct_conversation(&self, pk: PublicKey) -> Result<(), GossipError> {
+        self.direct_service.decline_request(&pk).await?;
+        Ok(())
+    }
+
     pub async fn set_pic(&self, pic_data: Vec<u8>) -> Result<(), GossipError> {
-        let pk = self.identity_service.get_default_identity_pk().await?;
+        let pk = self.identity_service.get_active_identity_pk().await?;
         self.identity_service.set_pic(pk, pic_data).await?;
         Ok(())
     }
+
+    /// Which identity we show up as in the current nearby group
+    pub async fn set_group_persona(&self, pk: PublicKey) -> Result<(), GossipError> {
+        self.nearby_service.set_group_persona(pk).await?;
+        Ok(())
+    }
+
+    /// Continue in the current group as a throwaway identity, which is
+    /// discarded when we leave it
+    pub async fn go_anonymous(&self) -> Result<Identity, GossipError> {
+        Ok(self.nearby_service.go_anonymous().await?)
+    }
+
+    pub async fn anonymous_in_new_groups(&self) -> Result<bool, GossipError> {
+        Ok(self.settings_service.get(&ANONYMOUS_IN_NEW_GROUPS).await?)
+    }
+
+    pub async fn set_anonymous_in_new_groups(&self, anonymous: bool) -> Result<(), GossipError> {
+        self.settings_service.set(&ANONYMOUS_IN_NEW_GROUPS, anonymous).await?;
+        Ok(())
+    }
+
+    /// Current value of anything from settings_registry()
+    pub async fn get_setting_value(&self, key: String) -> Result<StoreValue, GossipError> {
+        Ok(self.settings_service.get_value(&key).await?)
+    }
+
+    pub async fn set_setting_value(&self, key: String, value: StoreValue) -> Result<(), GossipError> {
+        self.settings_service.set_value(&key, value).await?;
+        Ok(())
+    }
+
+    /// Shareable settings, status and bio as json, for backing up or moving by hand
+    pub async fn export_settings_json(&self) -> Result<String, GossipError> {
+        Ok(self.settings_service.export_json().await?)
+    }
+
+    pub async fn import_settings_json(&self, json: String) -> Result<(), GossipError> {
+        self.settings_service.import_json(&json).await?;
+        Ok(())
+    }
 }
@@ -3,10 +3,10 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use anyhow::Error;
 use async_trait::async_trait;
 use tokio::sync::Notify;
+use tracing::warn;
 use crate::blob_dispatcher::{LoadCollectionDelegate, NamedBlob};
 use crate::data::{BlobHash, PublicKey};
-use crate::events::{start_with, Starter};
-use crate::identity::IdentityService;
+use crate::events::{Delivery, recv_event, start_with, Starter};
 use crate::identity::model::Identity;
 use crate::nearby::model::{BioDetails, NearbyProfile, Status};
 use crate::nearby::{NearbyService, NearbyServiceEvents};
@@ -38,18 +38,16 @@ pub trait NearbyDetailsViewModel: Send + Sync + 'static {
 pub struct NearbyDetailsViewController {
     subject_pk: PublicKey,
     nearby_service: NearbyService,
-    identity_service: IdentityService,
     settings_service: SettingsService,
     view_model: Arc<dyn NearbyDetailsViewModel>,
     stop: Arc<Notify>
 }
 
 impl NearbyDetailsViewController {
-    pub fn new(subject_pk: PublicKey, view_model: Arc<dyn NearbyDetailsViewModel>, identity_service: IdentityService, nearby_service: NearbyService, settings_service: SettingsService) -> Arc<Self> {
+    pub fn new(subject_pk: PublicKey, view_model: Arc<dyn NearbyDetailsViewModel>, nearby_service: NearbyService, settings_service: SettingsService) -> Arc<Self> {
         Arc::new(start_with(NearbyDetailsViewController {
             subject_pk,
             nearby_service,
-            identity_service,
             settings_service,
             view_model,
             stop: Arc::new(Notify::new())
@@ -68,7 +66,7 @@ impl Starter for NearbyDetailsViewController {
         self.view_model.initialized_updated(false).await;
         self.push_data_for_profile(&self.subject_pk).await?;
         self.view_model.initialized_updated(true).await;
-        self.listen().await;
+        self.listen().await?;
         Ok(())
     }
 }
@@ -83,7 +81,7 @@ impl NearbyDetailsViewController {
     }
 
     pub async fn set_share_bio(&self, should_share: bool) -> UIResponse {
-        self.settings_service.set_setting(SHARE_NEARBY_PUBLIC_BIO, should_share).await?;
+        self.settings_service.set(&SHARE_NEARBY_PUBLIC_BIO, should_share).await?;
         Ok(())
     }
 
@@ -97,8 +95,8 @@ impl NearbyDetailsViewController {
 
     async fn push_data_for_profile(&self, pk: &PublicKey) -> anyhow::Result<()> {
         let p = self.nearby_service.get_profile_by_key(pk).await?;
-        let me = self.identity_service.get_default_identity_pk().await?;
-        let share_it = self.settings_service.get_setting_defaulted(SHARE_NEARBY_PUBLIC_BIO, || false).await?;
+        let me = self.nearby_service.persona().await?;
+        let share_it = self.settings_service.get(&SHARE_NEARBY_PUBLIC_BIO).await?;
         self.view_model.name_updated(p.name).await;
         self.view_model.pic_updated(p.pic).await;
         self.view_model.status_update(p.status).await;
@@ -120,37 +118,38 @@ impl NearbyDetailsViewController {
         self.view_model.bio_details_updated(bio).await;
         Ok(())
     }
-    async fn listen(&self) {
+    async fn listen(&self) -> anyhow::Result<()> {
         let mut nearby_sub = self.nearby_service.subscribe();
         let mut setting_sub = self.settings_service.subscribe();
-        let me = self.identity_service.get_default_identity_pk().await.expect("getting my own identity");
+        let me = self.nearby_service.persona().await?;
 
         let mut listen = true;
         while listen {
             tokio::select! {
-                Ok(e) = setting_sub.recv() => {
-                    match e {
-                        SettingsEvent::OwnPublicBioUpdated(_) => {
-                            if me == self.subject_pk {
-                                if let Err(e) = self.push_data_for_profile(&self.subject_pk).await {
-                                    eprintln!("failed to push bio data {e}")
-                                }
-                            }
+                d = recv_event(&mut setting_sub) => {
+                    let refresh = match d {
+                        Delivery::Event(SettingsEvent::OwnPublicBioUpdated(_)) => me == self.subject_pk,
+                        Delivery::Event(_) => false,
+                        Delivery::Resync => true,
+                        Delivery::Closed => break
+                    };
+                    if refresh {
+                        if let Err(e) = self.push_data_for_profile(&self.subject_pk).await {
+                            warn!(subject = %self.subject_pk, "failed to push bio data: {e:#}")
                         }
-                        _ => {}
                     }
                 }
-                Ok(e) = nearby_sub.recv() => {
-                    match e {
-                        NearbyServiceEvents::BioUpdated(pk) => {
-                            if self.subject_pk != pk {
-                                break
-                            }
-                            if let Err(e) = self.push_data_for_profile(&self.subject_pk).await {
-                                eprintln!("failed to push bio data {e}")
-                            }
+                d = recv_event(&mut nearby_sub) => {
+                    let refresh = match d {
+                        Delivery::Event(NearbyServiceEvents::BioUpdated(pk)) => self.subject_pk == pk,
+                        Delivery::Event(_) => false,
+                        Delivery::Resync => true,
+                        Delivery::Closed => break
+                    };
+                    if refresh {
+                        if let Err(e) = self.push_data_for_profile(&self.subject_pk).await {
+                            warn!(subject = %self.subject_pk, "failed to push bio data: {e:#}")
                         }
-                        _ => {}
                     }
                 }
                 _ = self.stop.notified() => {
@@ -158,7 +157,7 @@ impl NearbyDetailsViewController {
                 }
             }
         }
-
+        Ok(())
     }
 
 }
@@ -1,32 +1,74 @@
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 
 use async_trait::async_trait;
 use futures_lite::StreamExt;
 use iroh::net::endpoint::ConnectionType;
+use serde::Serialize;
+use tokio::sync::Notify;
 
-use crate::data::PublicKey;
+use crate::data::{as_string, PublicKey};
 use crate::doc::Node;
 use crate::events::{start_with, Starter};
+use crate::lifecycle::Lifecycle;
 
-#[derive(uniffi::Record, Debug)]
+const DEFAULT_POLL_INTERVAL_MS: u64 = 200;
+
+#[derive(uniffi::Record, Serialize, Debug)]
 pub struct NodeStatsData {
+    #[serde(serialize_with = "as_string")]
     node_id: PublicKey,
-    relay_url: String,
+    relay_url: Option<String>,
     listen_addrs: Vec<String>,
     connections: Vec<ConnectionStats>
 }
 
-#[derive(uniffi::Record, Debug)]
-struct ConnectionStats {
+/// Times are None where iroh hasn't got one yet, eg. nothing received so far
+#[derive(uniffi::Record, Serialize, Debug)]
+pub struct ConnectionStats {
+    #[serde(serialize_with = "as_string")]
     node_id: PublicKey,
-    relay_info: String,
-    conn_type: String,
-    addrs: Vec<String>,
-    last_received: String,
+    relay: Option<RelayStats>,
+    conn_type: ConnType,
+    addrs: Vec<AddrStats>,
+    last_received: Option<Duration>,
     has_send_addr: bool
 }
+
+#[derive(uniffi::Record, Serialize, Debug)]
+pub struct RelayStats {
+    url: String,
+    latency_ms: Option<u64>,
+    last_alive: Option<Duration>
+}
+
+#[derive(uniffi::Record, Serialize, Debug)]
+pub struct AddrStats {
+    addr: String,
+    latency_ms: Option<u64>,
+    last_payload: Option<Duration>
+}
+
+#[derive(uniffi::Enum, Serialize, Debug)]
+pub enum ConnType {
+    Direct { addr: String },
+    Relay { url: String },
+    Mixed { addr: String, url: String },
+    None
+}
+
+impl From<&ConnectionType> for ConnType {
+    fn from(value: &ConnectionType) -> Self {
+        match value {
+            ConnectionType::Direct(addr) => ConnType::Direct { addr: addr.to_string() },
+            ConnectionType::Relay(url) => ConnType::Relay { url: url.to_string() },
+            ConnectionType::Mixed(addr, url) => ConnType::Mixed { addr: addr.to_string(), url: url.to_string() },
+            ConnectionType::None => ConnType::None
+        }
+    }
+}
+
 #[uniffi::export(with_foreign)]
 #[async_trait]
 pub trait NodeStatViewModel: Send + Sync + 'static {
@@ -37,21 +79,33 @@ pub trait NodeStatViewModel: Send + Sync + 'static {
 pub struct NodeStat {
     node: Node,
     view_model: Arc<dyn NodeStatViewModel>,
-    stop: Arc<AtomicBool>,
+    interval_ms: Arc<AtomicU64>,
+    lifecycle: Lifecycle,
+    stop: Arc<Notify>,
 }
 
 #[async_trait]
 impl Starter for NodeStat {
     async fn start(&self) -> Result<(), anyhow::Error> {
-        while !self.stop.load(Ordering::Relaxed) {
-            match self.generate_stats().await {
-                Ok(s) => {
-                    self.view_model.update_stats(s).await;
+        loop {
+            // nobody is looking at the stats while we're in the background
+            tokio::select! {
+                _ = self.lifecycle.foreground() => {}
+                _ = self.stop.notified() => {
+                    break;
+                }
+            }
+            if let Ok(s) = generate_stats(&self.node).await {
+                self.view_model.update_stats(s).await;
+            }
+            let interval = Duration::from_millis(self.interval_ms.load(Ordering::Relaxed));
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = self.stop.notified() => {
+                    break;
                 }
-                _ => {}
             }
-            tokio::time::sleep(Duration::from_millis(200)).await;
-        };
+        }
 
         Ok(())
     }
@@ -59,78 +113,70 @@ impl Starter for NodeStat {
 
 impl Drop for NodeStat {
     fn drop(&mut self) {
-        self.stop.store(true, Ordering::Relaxed);
+        // notify_one keeps the permit if the loop is busy polling, so it still stops
+        self.stop.notify_one();
     }
 }
 impl NodeStat {
-    pub fn new(view_model: Arc<dyn NodeStatViewModel>, node: Node) -> Self {
+    pub fn new(view_model: Arc<dyn NodeStatViewModel>, node: Node, lifecycle: Lifecycle) -> Self {
         start_with(NodeStat {
-            node, view_model, stop: Arc::new(AtomicBool::new(false))
+            node,
+            view_model,
+            interval_ms: Arc::new(AtomicU64::new(DEFAULT_POLL_INTERVAL_MS)),
+            lifecycle,
+            stop: Arc::new(Notify::new())
         })
     }
+}
 
-    async fn generate_stats(&self) -> Result<NodeStatsData, anyhow::Error> {
-        let node_id = self.node.node_id();
-        let node_status = self.node.node().status().await?;
-        let relay_url = node_status.addr.info.relay_url.map(|f|f.to_string()).unwrap_or(String::from("no relay"));
-        let listen_addrs: Vec<String> = node_status.listen_addrs.iter().map(|a| format!("{a}")).collect();
-
-        let mut stream = self.node.node().connections().await?;
-        let mut connections = vec![];
-        while let Some(Ok(ni)) = stream.next().await {
-            let node_id = ni.node_id.into();
-            let relay_info: String = ni.relay_url.as_ref().map(|rui| {
-
-                format!("{} (Latency: {}, LastAlive: {})", rui.relay_url.to_string(),
-                        format_duration(rui.latency),
-                        format_duration(rui.last_alive))
-
-            }).unwrap_or(String::default());
-            let conn_type = match &ni.conn_type {
-                ConnectionType::Direct(d) => {
-                    format!("Direct ({})", d)
-                }
-                ConnectionType::Relay(r) => {
-                    format!("Relay ({})", r.as_str())
-                }
-                ConnectionType::Mixed(a, r) => {
-                    format!("Mixed ({}, {})", a.to_string(), r.to_string())
-                }
-                ConnectionType::None => { String::from("none") }
-            };
-            let addrs: Vec<String> = ni.addrs.iter().map(|a| {
-                let lat = format_duration(a.latency);
-                let last = format_duration(a.last_payload);
-                let addr = a.addr.to_string();
-                format!("{addr} (lat: {lat}ms, last_payload: {last}ms)")
-            }).collect();
-
-            let last_received = format_duration(ni.last_received());
-            let has_send_addr = ni.has_send_address();
-
-            connections.push(ConnectionStats {
-                node_id,
-                relay_info,
-                conn_type,
-                addrs,
-                last_received,
-                has_send_addr
-            })
-        }
-
-        let stats = NodeStatsData {
-            node_id: node_id.into(),
-            relay_url,
-            listen_addrs,
-            connections
-        };
-
+#[uniffi::export]
+impl NodeStat {
+    /// Takes effect after the poll that's currently waiting
+    pub fn set_poll_interval(&self, interval_ms: u64) {
+        self.interval_ms.store(interval_ms.max(1), Ordering::Relaxed);
+    }
+}
 
-        Ok(stats)
+pub async fn generate_stats(node: &Node) -> Result<NodeStatsData, anyhow::Error> {
+    let node_id = node.node_id();
+    let node_status = node.node().status().await?;
+    let relay_url = node_status.addr.info.relay_url.map(|f| f.to_string());
+    let listen_addrs: Vec<String> = node_status.listen_addrs.iter().map(|a| format!("{a}")).collect();
+
+    let mut stream = node.node().connections().await?;
+    let mut connections = vec![];
+    while let Some(Ok(ni)) = stream.next().await {
+        let relay = ni.relay_url.as_ref().map(|rui| RelayStats {
+            url: rui.relay_url.to_string(),
+            latency_ms: millis(rui.latency),
+            last_alive: rui.last_alive
+        });
+        let addrs: Vec<AddrStats> = ni.addrs.iter().map(|a| AddrStats {
+            addr: a.addr.to_string(),
+            latency_ms: millis(a.latency),
+            last_payload: a.last_payload
+        }).collect();
+
+        connections.push(ConnectionStats {
+            node_id: ni.node_id.into(),
+            relay,
+            conn_type: (&ni.conn_type).into(),
+            addrs,
+            last_received: ni.last_received(),
+            has_send_addr: ni.has_send_address()
+        })
     }
 
+    let stats = NodeStatsData {
+        node_id: node_id.into(),
+        relay_url,
+        listen_addrs,
+        connections
+    };
+
+    Ok(stats)
 }
 
-fn format_duration(dur: Option<Duration>) -> String {
-    dur.map(|d|d.as_millis().to_string()).unwrap_or(String::from("∞"))
+fn millis(dur: Option<Duration>) -> Option<u64> {
+    dur.map(|d| d.as_millis() as u64)
 }
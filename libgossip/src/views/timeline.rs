@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use anyhow::Error;
+use async_trait::async_trait;
+use tokio::sync::Notify;
+
+use crate::events::{Delivery, recv_event, start_with, Starter};
+use crate::nearby::timeline::{Timeline, TimelineEntry};
+
+#[uniffi::export(with_foreign)]
+#[async_trait]
+pub trait ConnectionTimelineViewModel: Send + Sync + 'static {
+    /// The whole timeline, oldest first. Sent on start and whenever we fell behind
+    async fn timeline_updated(&self, entries: Vec<TimelineEntry>);
+
+    async fn entry_added(&self, entry: TimelineEntry);
+}
+
+/// Goes alongside NodeStat, which only shows how things are right now
+#[derive(uniffi::Object, Clone)]
+pub struct ConnectionTimelineViewController {
+    timeline: Timeline,
+    view_model: Arc<dyn ConnectionTimelineViewModel>,
+    stop: Arc<Notify>
+}
+
+impl ConnectionTimelineViewController {
+    pub fn new(view_model: Arc<dyn ConnectionTimelineViewModel>, timeline: Timeline) -> Arc<Self> {
+        Arc::new(start_with(ConnectionTimelineViewController {
+            timeline,
+            view_model,
+            stop: Arc::new(Notify::new())
+        }))
+    }
+}
+
+impl Drop for ConnectionTimelineViewController {
+    fn drop(&mut self) {
+        self.stop.notify_waiters();
+    }
+}
+
+#[async_trait]
+impl Starter for ConnectionTimelineViewController {
+    async fn start(&self) -> Result<(), Error> {
+        let (entries, mut sub) = self.timeline.subscribe_with_snapshot().await?;
+        self.view_model.timeline_updated(entries).await;
+
+        loop {
+            tokio::select! {
+                d = recv_event(&mut sub) => {
+                    match d {
+                        Delivery::Event(entry) => self.view_model.entry_added(entry).await,
+                        Delivery::Resync => self.view_model.timeline_updated(self.timeline.list().await).await,
+                        Delivery::Closed => break
+                    }
+                }
+                _ = self.stop.notified() => {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}